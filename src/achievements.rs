@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    browser,
+    game::{BoyEvent, ObstacleKind},
+};
+
+const STORAGE_KEY: &str = "walk_the_dog.achievements";
+
+// Something that happened during a run which achievements can count.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RunEvent {
+    Boy(BoyEvent),
+    Cleared(ObstacleKind),
+    Segment(String),
+    // The distance run so far, sent every update.
+    Ran(i32),
+}
+
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Trigger {
+    Jumped,
+    Slid,
+    Hurt,
+    Collected,
+    LandedOnPlatform,
+    Cleared(ObstacleKind),
+    Segment(String),
+}
+
+impl Trigger {
+    fn matches(&self, event: &RunEvent) -> bool {
+        match (self, event) {
+            (Trigger::Jumped, RunEvent::Boy(BoyEvent::Jumped | BoyEvent::DoubleJumped))
+            | (Trigger::Slid, RunEvent::Boy(BoyEvent::Slid))
+            | (Trigger::Hurt, RunEvent::Boy(BoyEvent::Hurt))
+            | (Trigger::Collected, RunEvent::Boy(BoyEvent::Collected(_)))
+            | (Trigger::LandedOnPlatform, RunEvent::Boy(BoyEvent::LandedOnPlatform)) => true,
+            (Trigger::Cleared(kind), RunEvent::Cleared(cleared)) => kind == cleared,
+            (Trigger::Segment(name), RunEvent::Segment(segment)) => name == segment,
+            _ => false,
+        }
+    }
+}
+
+// Counts build up over every run, while a distance has to be reached in a
+// single run without the trigger happening along the way.
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Condition {
+    Count { trigger: Trigger, target: u32 },
+    DistanceWithout { trigger: Trigger, distance: u32 },
+}
+
+impl Condition {
+    fn target(&self) -> u32 {
+        match self {
+            Condition::Count { target, .. } => *target,
+            Condition::DistanceWithout { distance, .. } => *distance,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Achievement {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub condition: Condition,
+}
+
+#[derive(Deserialize, Default, Clone)]
+pub struct AchievementList {
+    achievements: Vec<Achievement>,
+}
+
+impl TryFrom<wasm_bindgen::JsValue> for AchievementList {
+    type Error = Error;
+
+    fn try_from(json: wasm_bindgen::JsValue) -> Result<Self> {
+        serde_wasm_bindgen::from_value(json)
+            .map_err(|err| anyhow!("Could not deserialize json into achievements {:#?}", err))
+    }
+}
+
+// How far the player has got with each achievement, kept in the browser's
+// local storage between visits. For distances it's the best run so far.
+#[derive(Deserialize, Serialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct Progress {
+    values: HashMap<String, u32>,
+    unlocked: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct Achievements {
+    list: AchievementList,
+    progress: Progress,
+    // Distance achievements whose trigger has happened during this run.
+    spoiled: HashSet<String>,
+}
+
+impl Achievements {
+    pub fn new(list: AchievementList, progress: Progress) -> Self {
+        Achievements {
+            list,
+            progress,
+            spoiled: HashSet::new(),
+        }
+    }
+
+    pub fn load(list: AchievementList) -> Self {
+        let progress = browser::load(STORAGE_KEY)
+            .unwrap_or_else(|err| {
+                error!("Could not load achievements {:#?}", err);
+                None
+            })
+            .unwrap_or_default();
+        Achievements::new(list, progress)
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = browser::store(STORAGE_KEY, &self.progress) {
+            error!("Could not save achievements {:#?}", err);
+        }
+    }
+
+    // Returns the names of any achievements the event unlocked.
+    pub fn record(&mut self, event: &RunEvent) -> Vec<String> {
+        let Achievements {
+            list,
+            progress,
+            spoiled,
+        } = self;
+        let mut unlocked = vec![];
+        list.achievements.iter().for_each(|achievement| {
+            if progress.unlocked.contains(&achievement.id) {
+                return;
+            }
+            let value = progress.values.entry(achievement.id.clone()).or_default();
+            if advance(achievement, event, value, spoiled) {
+                progress.unlocked.insert(achievement.id.clone());
+                unlocked.push(achievement.name.clone());
+            }
+        });
+        unlocked
+    }
+
+    // Anything spoiled during a run can be tried for again in the next.
+    pub fn finish_run(&mut self) {
+        self.spoiled.clear();
+    }
+
+    // One line per achievement, showing what it asks for and how close the
+    // player is.
+    pub fn summaries(&self) -> Vec<String> {
+        self.list
+            .achievements
+            .iter()
+            .map(|achievement| {
+                if self.progress.unlocked.contains(&achievement.id) {
+                    format!("{}: done", achievement.description)
+                } else {
+                    let target = achievement.condition.target();
+                    let value = self
+                        .progress
+                        .values
+                        .get(&achievement.id)
+                        .copied()
+                        .unwrap_or_default();
+                    format!(
+                        "{}: {}/{}",
+                        achievement.description,
+                        value.min(target),
+                        target
+                    )
+                }
+            })
+            .collect()
+    }
+}
+
+// Moves an achievement on for the event, returning whether it has now
+// reached its target.
+fn advance(
+    achievement: &Achievement,
+    event: &RunEvent,
+    value: &mut u32,
+    spoiled: &mut HashSet<String>,
+) -> bool {
+    match (&achievement.condition, event) {
+        (Condition::Count { trigger, .. }, event) if trigger.matches(event) => *value += 1,
+        (Condition::DistanceWithout { trigger, .. }, event) if trigger.matches(event) => {
+            spoiled.insert(achievement.id.clone());
+        }
+        (Condition::DistanceWithout { .. }, RunEvent::Ran(distance))
+            if !spoiled.contains(&achievement.id) =>
+        {
+            *value = (*value).max((*distance).max(0) as u32);
+        }
+        _ => return false,
+    }
+    *value >= achievement.condition.target()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn achievements() -> Achievements {
+        let list: AchievementList = serde_json::from_str(
+            r#"{
+                "achievements": [
+                    {
+                        "id": "stones",
+                        "name": "Stone Hopper",
+                        "description": "Clear 2 stones",
+                        "condition": { "count": { "trigger": { "cleared": "stone" }, "target": 2 } }
+                    },
+                    {
+                        "id": "grounded",
+                        "name": "Feet on the Ground",
+                        "description": "Run 500 without landing on a platform",
+                        "condition": {
+                            "distanceWithout": { "trigger": "landedOnPlatform", "distance": 500 }
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        Achievements::new(list, Progress::default())
+    }
+
+    #[test]
+    fn counts_unlock_once_when_they_reach_their_target() {
+        let mut achievements = achievements();
+        let stone = RunEvent::Cleared(ObstacleKind::Stone);
+        assert!(achievements.record(&stone).is_empty());
+        assert!(achievements
+            .record(&RunEvent::Cleared(ObstacleKind::Platform))
+            .is_empty());
+        assert_eq!(achievements.summaries()[0], "Clear 2 stones: 1/2");
+
+        assert_eq!(achievements.record(&stone), ["Stone Hopper"]);
+        assert!(achievements.record(&stone).is_empty());
+        assert_eq!(achievements.summaries()[0], "Clear 2 stones: done");
+    }
+
+    #[test]
+    fn distances_must_be_reached_without_the_trigger_in_the_same_run() {
+        let mut achievements = achievements();
+        achievements.record(&RunEvent::Ran(300));
+        achievements.record(&RunEvent::Boy(BoyEvent::LandedOnPlatform));
+        assert!(achievements.record(&RunEvent::Ran(600)).is_empty());
+        assert_eq!(
+            achievements.summaries()[1],
+            "Run 500 without landing on a platform: 300/500"
+        );
+
+        achievements.finish_run();
+        assert_eq!(
+            achievements.record(&RunEvent::Ran(500)),
+            ["Feet on the Ground"]
+        );
+    }
+
+    #[test]
+    fn shipped_achievements_load() {
+        let list: AchievementList =
+            serde_json::from_str(include_str!("../static/achievements.json")).unwrap();
+        assert!(!Achievements::new(list, Progress::default())
+            .summaries()
+            .is_empty());
+    }
+}
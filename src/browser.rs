@@ -2,6 +2,7 @@ use std::future::Future;
 
 use anyhow::{anyhow, Result};
 use js_sys::ArrayBuffer;
+use serde::{de::DeserializeOwned, Serialize};
 use wasm_bindgen::{
     closure::{IntoWasmClosure, WasmClosure, WasmClosureFnOnce},
     prelude::*,
@@ -9,8 +10,8 @@ use wasm_bindgen::{
 };
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    CanvasRenderingContext2d, Document, Element, HtmlCanvasElement, HtmlElement, HtmlImageElement,
-    Response, Window,
+    CanvasRenderingContext2d, Document, Element, EventTarget, HtmlCanvasElement, HtmlImageElement,
+    HtmlInputElement, HtmlTextAreaElement, Response, Storage, UrlSearchParams, Window,
 };
 
 macro_rules! log {
@@ -25,6 +26,8 @@ macro_rules! error {
     };
 }
 
+pub mod ui;
+
 pub fn window() -> Result<Window> {
     web_sys::window().ok_or_else(|| anyhow!("No window found"))
 }
@@ -57,6 +60,16 @@ pub fn context() -> Result<CanvasRenderingContext2d> {
         })
 }
 
+pub fn query_parameter(name: &str) -> Result<Option<String>> {
+    let search = window()?
+        .location()
+        .search()
+        .map_err(|err| anyhow!("Could not read the page's query string {:#?}", err))?;
+    Ok(UrlSearchParams::new_with_str(&search)
+        .map_err(|err| anyhow!("Could not parse the page's query string {:#?}", err))?
+        .get(name))
+}
+
 pub fn spawn_local<F>(future: F)
 where
     F: Future<Output = ()> + 'static,
@@ -100,6 +113,45 @@ pub async fn fetch_array_buffer(resource: &str) -> Result<ArrayBuffer> {
         .map_err(|err| anyhow!("Error converting raw JsValue to ArrayBuffer {:#?}", err))
 }
 
+pub fn to_json_string<T: Serialize>(value: &T) -> Result<String> {
+    let js_value = serde_wasm_bindgen::to_value(value)
+        .map_err(|err| anyhow!("Could not serialize value {:#?}", err))?;
+    js_sys::JSON::stringify_with_replacer_and_space(&js_value, &JsValue::NULL, &JsValue::from(2))
+        .map(String::from)
+        .map_err(|err| anyhow!("Could not convert value to JSON {:#?}", err))
+}
+
+fn local_storage() -> Result<Storage> {
+    window()?
+        .local_storage()
+        .map_err(|err| anyhow!("Could not access local storage {:#?}", err))?
+        .ok_or_else(|| anyhow!("No local storage found"))
+}
+
+// Reads back a value stored with `store`, or None if nothing has been stored
+// under `key` yet.
+pub fn load<T: DeserializeOwned>(key: &str) -> Result<Option<T>> {
+    match local_storage()?
+        .get_item(key)
+        .map_err(|err| anyhow!("Could not read {} from local storage {:#?}", key, err))?
+    {
+        Some(json) => {
+            let js_value = js_sys::JSON::parse(&json)
+                .map_err(|err| anyhow!("Could not parse stored {} {:#?}", key, err))?;
+            serde_wasm_bindgen::from_value(js_value)
+                .map(Some)
+                .map_err(|err| anyhow!("Could not deserialize stored {} {:#?}", key, err))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn store<T: Serialize>(key: &str, value: &T) -> Result<()> {
+    local_storage()?
+        .set_item(key, &to_json_string(value)?)
+        .map_err(|err| anyhow!("Could not write {} to local storage {:#?}", key, err))
+}
+
 pub fn new_image() -> Result<HtmlImageElement> {
     HtmlImageElement::new().map_err(|err| anyhow!("Could not create HtmlImageElement {:#?}", err))
 }
@@ -137,26 +189,21 @@ pub fn now() -> Result<f64> {
         .now())
 }
 
-pub fn draw_ui(html: &str) -> Result<()> {
-    find_ui()?
-        .insert_adjacent_html("afterbegin", html)
-        .map_err(|err| anyhow!("Could not insert html {:#?}", err))
-}
-
-pub fn hide_ui() -> Result<()> {
-    let ui = find_ui()?;
-    if let Some(child) = ui.first_child() {
-        ui.remove_child(&child)
-            .map(|_removed_child| ())
-            .map_err(|err| anyhow!("Failed to remove child {:#?}", err))
-            .and_then(|_unit| {
-                canvas()?
-                    .focus()
-                    .map_err(|err| anyhow!("Could not set focus to canvas {:#?}", err))
-            })
-    } else {
-        Ok(())
-    }
+// Today's date in UTC as year-month-day, the same wherever the player is.
+pub fn utc_date() -> String {
+    let date = js_sys::Date::new_0();
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.get_utc_full_year(),
+        date.get_utc_month() + 1,
+        date.get_utc_date()
+    )
+}
+
+pub fn is_text_field(target: Option<EventTarget>) -> bool {
+    target.is_some_and(|target| {
+        target.has_type::<HtmlInputElement>() || target.has_type::<HtmlTextAreaElement>()
+    })
 }
 
 fn find_ui() -> Result<Element> {
@@ -166,19 +213,6 @@ fn find_ui() -> Result<Element> {
     })
 }
 
-pub fn find_html_element_by_id(id: &str) -> Result<HtmlElement> {
-    document()
-        .and_then(|doc| {
-            doc.get_element_by_id(id)
-                .ok_or_else(|| anyhow!("Element with id {} not found", id))
-        })
-        .and_then(|element| {
-            element
-                .dyn_into::<HtmlElement>()
-                .map_err(|err| anyhow!("Could not cast into HtmlElement {:#?}", err))
-        })
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{HtmlElement, HtmlInputElement};
+
+use super::{canvas, closure_wrap, document, find_ui};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum UiEvent<K> {
+    Clicked(K),
+    Changed(K, String),
+}
+
+// A block of UI over the canvas, built from typed widgets rather than HTML
+// strings. Widgets that can be interacted with are given a key, and what
+// happens to them comes back through `next_event` tagged with it. Dropping
+// the panel removes it from the page along with every listener it added.
+pub struct Panel<K> {
+    root: HtmlElement,
+    listeners: Vec<(HtmlElement, Closure<dyn FnMut()>)>,
+    sender: UnboundedSender<UiEvent<K>>,
+    events: UnboundedReceiver<UiEvent<K>>,
+}
+
+impl<K: Clone + 'static> Panel<K> {
+    pub fn new() -> Result<Self> {
+        let root = create_element("div")?;
+        find_ui()?
+            .append_child(&root)
+            .map_err(|err| anyhow!("Could not add panel to the UI {:#?}", err))?;
+        let (sender, events) = unbounded();
+        Ok(Panel {
+            root,
+            listeners: vec![],
+            sender,
+            events,
+        })
+    }
+
+    pub fn label(&mut self, text: &str) -> Result<()> {
+        let label = create_element("p")?;
+        label.set_text_content(Some(text));
+        self.add(&label)
+    }
+
+    pub fn button(&mut self, key: K, text: &str) -> Result<()> {
+        let button = create_element("button")?;
+        button.set_text_content(Some(text));
+        self.add(&button)?;
+        let sender = self.sender.clone();
+        let on_click: Closure<dyn FnMut()> = closure_wrap(move || {
+            if let Err(err) = sender.unbounded_send(UiEvent::Clicked(key.clone())) {
+                error!("Could not send click event {:#?}", err);
+            }
+        });
+        button.set_onclick(Some(on_click.as_ref().unchecked_ref()));
+        self.listeners.push((button, on_click));
+        Ok(())
+    }
+
+    // A single line of text, reporting its contents each time they change.
+    pub fn input(&mut self, key: K, value: &str) -> Result<()> {
+        let input: HtmlInputElement = create_element("input")?
+            .dyn_into()
+            .map_err(|err| anyhow!("Could not cast into HtmlInputElement {:#?}", err))?;
+        input.set_value(value);
+        self.add(&input)?;
+        let sender = self.sender.clone();
+        let source = input.clone();
+        let on_input: Closure<dyn FnMut()> = closure_wrap(move || {
+            if let Err(err) = sender.unbounded_send(UiEvent::Changed(key.clone(), source.value())) {
+                error!("Could not send input event {:#?}", err);
+            }
+        });
+        input.set_oninput(Some(on_input.as_ref().unchecked_ref()));
+        self.listeners.push((input.into(), on_input));
+        Ok(())
+    }
+
+    // Text to read or copy, which can't be edited.
+    pub fn text_area(&mut self, text: &str, rows: u32, cols: u32) -> Result<()> {
+        let text_area = create_element("textarea")?;
+        text_area.set_text_content(Some(text));
+        [
+            ("readonly", String::new()),
+            ("rows", rows.to_string()),
+            ("cols", cols.to_string()),
+        ]
+        .iter()
+        .try_for_each(|(name, value)| {
+            text_area
+                .set_attribute(name, value)
+                .map_err(|err| anyhow!("Could not set {} on text area {:#?}", name, err))
+        })?;
+        self.add(&text_area)
+    }
+
+    pub fn next_event(&mut self) -> Option<UiEvent<K>> {
+        self.events.try_next().ok().flatten()
+    }
+
+    fn add(&self, element: &HtmlElement) -> Result<()> {
+        self.root
+            .append_child(element)
+            .map(|_node| ())
+            .map_err(|err| anyhow!("Could not add element to panel {:#?}", err))
+    }
+}
+
+impl<K> Drop for Panel<K> {
+    fn drop(&mut self) {
+        self.listeners.iter().for_each(|(element, _listener)| {
+            element.set_onclick(None);
+            element.set_oninput(None);
+        });
+        self.root.remove();
+        if let Err(err) = canvas().and_then(|canvas| {
+            canvas
+                .focus()
+                .map_err(|err| anyhow!("Could not set focus to canvas {:#?}", err))
+        }) {
+            error!("Could not return focus after closing a panel {:#?}", err);
+        }
+    }
+}
+
+fn create_element(tag: &str) -> Result<HtmlElement> {
+    document()?
+        .create_element(tag)
+        .map_err(|err| anyhow!("Could not create {} element {:#?}", tag, err))?
+        .dyn_into::<HtmlElement>()
+        .map_err(|err| anyhow!("Could not cast into HtmlElement {:#?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    #[allow(dead_code)]
+    fn test_panel_events_and_teardown() {
+        document()
+            .unwrap()
+            .body()
+            .unwrap()
+            .insert_adjacent_html("afterbegin", "<div id='ui'></div>")
+            .unwrap();
+        let mut panel = Panel::new().unwrap();
+        panel.label("Paused").unwrap();
+        panel.button("resume", "Resume").unwrap();
+        find_ui()
+            .unwrap()
+            .query_selector("button")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<HtmlElement>()
+            .unwrap()
+            .click();
+        assert_eq!(panel.next_event(), Some(UiEvent::Clicked("resume")));
+        assert_eq!(panel.next_event(), None);
+
+        drop(panel);
+        assert_eq!(find_ui().unwrap().child_element_count(), 0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::browser;
+
+const STORAGE_KEY: &str = "walk_the_dog.daily";
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// The course seed for a day's challenge. It's worked out from the date alone,
+// with a hash that doesn't change between builds, so every player gets the
+// same course on the same day.
+pub fn seed(date: &str) -> u64 {
+    date.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// The score from the first attempt at each day's challenge, kept in the
+// browser's local storage. Later attempts on the same day are only practice.
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct DailyScores {
+    scores: BTreeMap<String, u32>,
+}
+
+impl DailyScores {
+    pub fn load() -> Self {
+        browser::load(STORAGE_KEY)
+            .unwrap_or_else(|err| {
+                error!("Could not load daily scores {:#?}", err);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = browser::store(STORAGE_KEY, self) {
+            error!("Could not save daily scores {:#?}", err);
+        }
+    }
+
+    pub fn score(&self, date: &str) -> Option<u32> {
+        self.scores.get(date).copied()
+    }
+
+    // Returns whether this was the day's scored attempt.
+    pub fn record(&mut self, date: &str, score: u32) -> bool {
+        if self.scores.contains_key(date) {
+            return false;
+        }
+        self.scores.insert(date.to_string(), score);
+        true
+    }
+
+    // The most recent days first. Dates are kept as year-month-day, so they
+    // sort in the order they happened.
+    pub fn recent(&self) -> impl Iterator<Item = (&String, &u32)> {
+        self.scores.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_day_has_its_own_course() {
+        assert_eq!(seed("2026-10-18"), seed("2026-10-18"));
+        assert_ne!(seed("2026-10-18"), seed("2026-10-19"));
+    }
+
+    #[test]
+    fn only_the_first_attempt_each_day_is_scored() {
+        let mut daily = DailyScores::default();
+        assert!(daily.record("2026-10-17", 40));
+        assert!(daily.record("2026-10-18", 25));
+        assert!(!daily.record("2026-10-18", 90));
+        assert_eq!(daily.score("2026-10-18"), Some(25));
+
+        let recent: Vec<&String> = daily.recent().map(|(date, _score)| date).collect();
+        assert_eq!(recent, ["2026-10-18", "2026-10-17"]);
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+
+use crate::game::Ability;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Stage {
+    pub distance: i32,
+    pub running_speed: i16,
+    pub obstacle_buffer: i16,
+    pub max_segment_difficulty: u8,
+    #[serde(default)]
+    pub unlocks: Vec<Ability>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct DifficultyCurve {
+    stages: Vec<Stage>,
+}
+
+impl TryFrom<wasm_bindgen::JsValue> for DifficultyCurve {
+    type Error = Error;
+
+    fn try_from(json: wasm_bindgen::JsValue) -> Result<Self> {
+        let curve: DifficultyCurve = serde_wasm_bindgen::from_value(json)
+            .map_err(|_err| anyhow!("Could not deserialize json into a DifficultyCurve"))?;
+        DifficultyCurve::new(curve.stages)
+    }
+}
+
+impl DifficultyCurve {
+    pub fn new(mut stages: Vec<Stage>) -> Result<Self> {
+        stages.sort_by_key(|stage| stage.distance);
+        match stages.first() {
+            Some(first) if first.distance == 0 => Ok(DifficultyCurve { stages }),
+            Some(_) => Err(anyhow!(
+                "The first difficulty stage must start at distance 0"
+            )),
+            None => Err(anyhow!("The difficulty curve has no stages")),
+        }
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    fn stage_index_at(&self, distance: i32) -> usize {
+        self.stages
+            .iter()
+            .rposition(|stage| stage.distance <= distance)
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone)]
+pub struct DifficultyDirector {
+    curve: DifficultyCurve,
+    stage_index: usize,
+}
+
+impl DifficultyDirector {
+    pub fn new(curve: DifficultyCurve) -> Self {
+        DifficultyDirector {
+            curve,
+            stage_index: 0,
+        }
+    }
+
+    pub fn reset(director: Self) -> Self {
+        DifficultyDirector::new(director.curve)
+    }
+
+    pub fn update(&mut self, distance: i32) {
+        self.stage_index = self.curve.stage_index_at(distance);
+    }
+
+    pub fn stage(&self) -> &Stage {
+        &self.curve.stages()[self.stage_index]
+    }
+
+    pub fn stage_number(&self) -> usize {
+        self.stage_index + 1
+    }
+
+    // Every ability from the stages reached so far, so none are missed if a
+    // stage is skipped between two updates.
+    pub fn unlocked_abilities(&self) -> impl Iterator<Item = &Ability> {
+        self.curve.stages[..=self.stage_index]
+            .iter()
+            .flat_map(|stage| stage.unlocks.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(distance: i32, running_speed: i16, unlocks: Vec<Ability>) -> Stage {
+        Stage {
+            distance,
+            running_speed,
+            obstacle_buffer: 20,
+            max_segment_difficulty: 1,
+            unlocks,
+        }
+    }
+
+    #[test]
+    fn director_moves_through_stages_as_distance_grows() {
+        let curve = DifficultyCurve::new(vec![
+            stage(3000, 5, vec![Ability::DoubleJump]),
+            stage(0, 4, vec![]),
+            stage(6000, 6, vec![Ability::FastFall]),
+        ])
+        .unwrap();
+        let mut director = DifficultyDirector::new(curve);
+
+        director.update(2999);
+        assert_eq!(director.stage().running_speed, 4);
+        assert_eq!(director.unlocked_abilities().count(), 0);
+
+        director.update(7000);
+        assert_eq!(director.stage().running_speed, 6);
+        assert_eq!(
+            director.unlocked_abilities().copied().collect::<Vec<_>>(),
+            vec![Ability::DoubleJump, Ability::FastFall]
+        );
+    }
+
+    #[test]
+    fn curve_must_start_at_zero() {
+        assert!(DifficultyCurve::new(vec![stage(100, 4, vec![])]).is_err());
+    }
+}
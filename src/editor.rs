@@ -0,0 +1,175 @@
+use crate::engine::{Point, Rect, SpriteSheet};
+use crate::game::{lay_out_tiles, tile_bounding_box};
+use crate::segments::{PlatformPlacement, SegmentDefinition, SegmentSet};
+
+pub const GRID: i16 = 10;
+
+#[derive(Clone)]
+pub enum DraftItem {
+    Barrier(Point),
+    Platform(PlatformPlacement),
+}
+
+enum Edit {
+    Add(DraftItem),
+    Remove(usize, DraftItem),
+}
+
+// A segment being built in the editor, with every change recorded so it can
+// be undone and redone.
+#[derive(Default)]
+pub struct SegmentDraft {
+    items: Vec<DraftItem>,
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+}
+
+impl SegmentDraft {
+    pub fn items(&self) -> &[DraftItem] {
+        &self.items
+    }
+
+    pub fn place(&mut self, item: DraftItem) {
+        self.items.push(item.clone());
+        self.undo_stack.push(Edit::Add(item));
+        self.redo_stack.clear();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.items.len() {
+            let item = self.items.remove(index);
+            self.undo_stack.push(Edit::Remove(index, item));
+            self.redo_stack.clear();
+        }
+    }
+
+    pub fn undo(&mut self) {
+        match self.undo_stack.pop() {
+            Some(Edit::Add(item)) => {
+                self.items.pop();
+                self.redo_stack.push(Edit::Add(item));
+            }
+            Some(Edit::Remove(index, item)) => {
+                self.items.insert(index, item.clone());
+                self.redo_stack.push(Edit::Remove(index, item));
+            }
+            None => {}
+        }
+    }
+
+    pub fn redo(&mut self) {
+        match self.redo_stack.pop() {
+            Some(Edit::Add(item)) => {
+                self.items.push(item.clone());
+                self.undo_stack.push(Edit::Add(item));
+            }
+            Some(Edit::Remove(index, item)) => {
+                self.items.remove(index);
+                self.undo_stack.push(Edit::Remove(index, item));
+            }
+            None => {}
+        }
+    }
+
+    pub fn item_at(
+        &self,
+        point: Point,
+        segment_set: &SegmentSet,
+        sprite_sheet: &SpriteSheet,
+        stone_size: (i16, i16),
+    ) -> Option<usize> {
+        let target = Rect::new(point, 1, 1);
+        self.items.iter().rposition(|item| match item {
+            DraftItem::Barrier(position) => {
+                Rect::new(*position, stone_size.0, stone_size.1).intersects(&target)
+            }
+            DraftItem::Platform(placement) => {
+                segment_set
+                    .platform(&placement.platform)
+                    .map_or(false, |definition| {
+                        let position = Point {
+                            x: placement.x,
+                            y: placement.y,
+                        };
+                        lay_out_tiles(&definition.rows(placement.length), |sprite_name| {
+                            sprite_sheet.cell(sprite_name)
+                        })
+                        .iter()
+                        .any(|(cell, offset)| {
+                            tile_bounding_box(cell, position, *offset).intersects(&target)
+                        })
+                    })
+            }
+        })
+    }
+
+    pub fn to_definition(&self, name: &str) -> SegmentDefinition {
+        SegmentDefinition {
+            name: name.into(),
+            difficulty: 1,
+            weight: 1,
+            requires: None,
+            cannot_follow: vec![],
+            cannot_precede: vec![],
+            barriers: self
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    DraftItem::Barrier(position) => Some(*position),
+                    DraftItem::Platform(_) => None,
+                })
+                .collect(),
+            platforms: self
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    DraftItem::Barrier(_) => None,
+                    DraftItem::Platform(placement) => Some(placement.clone()),
+                })
+                .collect(),
+            collectibles: vec![],
+            pits: vec![],
+            ceilings: vec![],
+        }
+    }
+}
+
+pub fn snap(value: i16) -> i16 {
+    (value + GRID / 2).div_euclid(GRID) * GRID
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_replay_places_and_removes() {
+        let mut draft = SegmentDraft::default();
+        draft.place(DraftItem::Barrier(Point { x: 100, y: 496 }));
+        draft.place(DraftItem::Barrier(Point { x: 200, y: 496 }));
+        draft.remove(0);
+        assert_eq!(draft.to_definition("test").barriers[0].x, 200);
+
+        draft.undo();
+        draft.undo();
+        assert_eq!(draft.items().len(), 1);
+        assert_eq!(draft.to_definition("test").barriers[0].x, 100);
+
+        draft.redo();
+        draft.redo();
+        assert_eq!(draft.items().len(), 1);
+        assert_eq!(draft.to_definition("test").barriers[0].x, 200);
+
+        draft.undo();
+        draft.place(DraftItem::Barrier(Point { x: 300, y: 496 }));
+        draft.redo();
+        assert_eq!(draft.items().len(), 3);
+    }
+
+    #[test]
+    fn snap_rounds_to_the_nearest_grid_line() {
+        assert_eq!(snap(14), 10);
+        assert_eq!(snap(15), 20);
+        assert_eq!(snap(-4), 0);
+    }
+}
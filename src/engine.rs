@@ -2,13 +2,15 @@ use anyhow::{anyhow, Error, Result};
 use async_trait::async_trait;
 use futures::channel::mpsc::{unbounded, UnboundedReceiver};
 use futures::channel::oneshot::channel;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{rc::Rc, sync::Mutex};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{AudioBuffer, AudioContext, CanvasRenderingContext2d, HtmlElement, HtmlImageElement};
+use web_sys::{
+    AudioBuffer, AudioContext, CanvasRenderingContext2d, GainNode, HtmlElement, HtmlImageElement,
+};
 
 use crate::browser::{self, LoopClosure};
 use crate::sound;
@@ -18,6 +20,12 @@ pub trait Game {
     async fn initialize(&self) -> Result<Box<dyn Game>>;
     fn update(&mut self, keystate: &KeyState);
     fn draw(&self, renderer: &Renderer);
+
+    // How fast game time runs against real time. Below 1.0 updates come
+    // less often, and keys pressed in between are kept for the next one.
+    fn time_scale(&self) -> f32 {
+        1.0
+    }
 }
 
 const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
@@ -48,9 +56,10 @@ impl GameLoop {
         *g.borrow_mut() = Some(browser::create_raf_closure(move |perf: f64| {
             process_input(&mut keystate, &mut keyevent_receiver);
             let frame_time = perf - game_loop.last_frame;
-            game_loop.accumulated_delta += frame_time as f32;
+            game_loop.accumulated_delta += frame_time as f32 * game.time_scale();
             while game_loop.accumulated_delta > FRAME_SIZE {
                 game.update(&keystate);
+                keystate.clear_just_pressed();
                 game_loop.accumulated_delta -= FRAME_SIZE;
             }
             game_loop.last_frame = perf;
@@ -75,7 +84,7 @@ impl GameLoop {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 pub struct Rect {
     pub position: Point,
     pub width: i16,
@@ -157,6 +166,43 @@ impl Renderer {
             .expect("Drawing is throwing exceptions! Unrecoverable error")
     }
 
+    // Draws see-through, where an alpha of 0.0 is invisible and 1.0 is solid.
+    pub fn draw_image_with_alpha(
+        &self,
+        image: &HtmlImageElement,
+        frame: &Rect,
+        destination: &Rect,
+        alpha: f64,
+    ) {
+        self.context.set_global_alpha(alpha);
+        self.draw_image(image, frame, destination);
+        self.context.set_global_alpha(1.0);
+    }
+
+    // Draws into one part of the canvas as if it were a canvas of its own.
+    // Everything `draw` does is scaled from the viewport's top left and
+    // clipped to its edges.
+    pub fn draw_in_viewport(&self, viewport: &Rect, scale: f64, draw: impl FnOnce(&Renderer)) {
+        self.context.save();
+        self.context.begin_path();
+        self.context.rect(
+            viewport.x().into(),
+            viewport.y().into(),
+            viewport.width.into(),
+            viewport.height.into(),
+        );
+        self.context.clip();
+        match self
+            .context
+            .translate(viewport.x().into(), viewport.y().into())
+            .and_then(|_unit| self.context.scale(scale, scale))
+        {
+            Ok(()) => draw(self),
+            Err(err) => error!("Could not place viewport {:#?}", err),
+        }
+        self.context.restore();
+    }
+
     pub fn draw_entire_image(&self, image: &HtmlImageElement, position: &Point) {
         self.context
             .draw_image_with_html_image_element(image, position.x.into(), position.y.into())
@@ -176,6 +222,27 @@ impl Renderer {
         self.context.stroke();
     }
 
+    pub fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.context.set_fill_style_str(color);
+        self.context.fill_rect(
+            rect.x().into(),
+            rect.y().into(),
+            rect.width.into(),
+            rect.height.into(),
+        );
+        self.context.set_fill_style_str("black");
+    }
+
+    pub fn draw_line(&self, from: &Point, to: &Point, color: &str, width: f64) {
+        self.context.set_stroke_style_str(color);
+        self.context.set_line_width(width);
+        self.context.begin_path();
+        self.context.move_to(from.x.into(), from.y.into());
+        self.context.line_to(to.x.into(), to.y.into());
+        self.context.stroke();
+        self.context.set_line_width(1.0);
+    }
+
     #[allow(dead_code)]
     pub fn draw_text(&self, text: &str, location: &Point) -> Result<()> {
         self.context.set_font("16pt serif");
@@ -185,6 +252,28 @@ impl Renderer {
 
         Ok(())
     }
+
+    // Draws text centred on `location` in any font and colour, leaving the
+    // context as `draw_text` expects to find it.
+    pub fn draw_centered_text(
+        &self,
+        text: &str,
+        location: &Point,
+        font: &str,
+        color: &str,
+    ) -> Result<()> {
+        self.context.set_font(font);
+        self.context.set_fill_style_str(color);
+        self.context.set_text_align("center");
+        let result = self
+            .context
+            .fill_text(text, location.x.into(), location.y.into())
+            .map_err(|err| anyhow!("Error filling text {:#?}", err));
+        self.context.set_text_align("start");
+        self.context.set_fill_style_str("black");
+
+        result
+    }
 }
 
 pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
@@ -222,12 +311,14 @@ enum KeyPress {
 
 pub struct KeyState {
     pressed_keys: HashMap<String, web_sys::KeyboardEvent>,
+    just_pressed_keys: HashSet<String>,
 }
 
 impl KeyState {
     fn new() -> Self {
         KeyState {
             pressed_keys: HashMap::new(),
+            just_pressed_keys: HashSet::new(),
         }
     }
 
@@ -235,10 +326,26 @@ impl KeyState {
         self.pressed_keys.contains_key(code)
     }
 
+    // True only for the first update after the key went down, ignoring key repeat.
+    pub fn is_just_pressed(&self, code: &str) -> bool {
+        self.just_pressed_keys.contains(code)
+    }
+
+    pub fn just_pressed(&self) -> impl Iterator<Item = &str> {
+        self.just_pressed_keys.iter().map(String::as_str)
+    }
+
     fn set_pressed(&mut self, code: &str, event: web_sys::KeyboardEvent) {
+        if !self.is_pressed(code) {
+            self.just_pressed_keys.insert(code.into());
+        }
         self.pressed_keys.insert(code.into(), event);
     }
 
+    fn clear_just_pressed(&mut self) {
+        self.just_pressed_keys.clear();
+    }
+
     fn set_released(&mut self, code: &str) {
         self.pressed_keys.remove(code);
     }
@@ -250,6 +357,10 @@ fn prepare_input() -> Result<UnboundedReceiver<KeyPress>> {
     let keyup_sender = Rc::clone(&keydown_sender);
     let onkeydown: Closure<dyn FnMut(web_sys::KeyboardEvent)> =
         browser::closure_wrap(move |keycode: web_sys::KeyboardEvent| {
+            // Typing into a text field on the UI isn't meant for the game.
+            if browser::is_text_field(keycode.target()) {
+                return;
+            }
             if let Err(err) = keydown_sender
                 .borrow_mut()
                 .start_send(KeyPress::KeyDown(keycode))
@@ -289,7 +400,7 @@ fn process_input(state: &mut KeyState, keyevent_receiver: &mut UnboundedReceiver
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct Point {
     pub x: i16,
     pub y: i16,
@@ -324,6 +435,13 @@ impl TryFrom<wasm_bindgen::JsValue> for Sheet {
     }
 }
 
+impl Sheet {
+    pub fn cell(&self, name: &str) -> Option<&Cell> {
+        self.frames.get(name)
+    }
+}
+
+#[derive(Clone)]
 pub struct Image {
     element: HtmlImageElement,
     bounding_box: Rect,
@@ -370,7 +488,7 @@ impl SpriteSheet {
     }
 
     pub fn cell(&self, name: &str) -> Option<&Cell> {
-        self.sheet.frames.get(name)
+        self.sheet.cell(name)
     }
 
     pub fn draw(&self, renderer: &Renderer, source: &Rect, destination: &Rect) {
@@ -381,12 +499,17 @@ impl SpriteSheet {
 #[derive(Clone)]
 pub struct Audio {
     context: AudioContext,
+    music: GainNode,
+    effects: GainNode,
 }
 
 impl Audio {
     pub fn new() -> Result<Self> {
+        let context = sound::create_audio_context()?;
         Ok(Audio {
-            context: sound::create_audio_context()?,
+            music: sound::create_gain(&context)?,
+            effects: sound::create_gain(&context)?,
+            context,
         })
     }
 
@@ -399,11 +522,37 @@ impl Audio {
     }
 
     pub fn play_sound(&self, sound: &Sound) -> Result<()> {
-        sound::play_sound(&self.context, &sound.buffer, sound::Looping::No)
+        sound::play_sound(
+            &self.context,
+            &sound.buffer,
+            &self.effects,
+            sound::Looping::No,
+        )
     }
 
     pub fn play_looping_sound(&self, sound: &Sound) -> Result<()> {
-        sound::play_sound(&self.context, &sound.buffer, sound::Looping::Yes)
+        sound::play_sound(
+            &self.context,
+            &sound.buffer,
+            &self.music,
+            sound::Looping::Yes,
+        )
+    }
+
+    // Looping sounds are music and everything else is an effect, each with
+    // its own volume from 0.0 to 1.0.
+    pub fn set_volumes(&self, music: f32, effects: f32) {
+        self.music.gain().set_value(music);
+        self.effects.gain().set_value(effects);
+    }
+
+    // Browsers keep audio suspended until the player has interacted with the
+    // page, so this is called once they have.
+    pub fn resume(&self) -> Result<()> {
+        self.context
+            .resume()
+            .map(|_promise| ())
+            .map_err(|err| anyhow!("Could not resume audio {:#?}", err))
     }
 }
 
@@ -412,17 +561,54 @@ pub struct Sound {
     pub buffer: AudioBuffer,
 }
 
-pub fn add_click_handler(elem: HtmlElement) -> UnboundedReceiver<()> {
-    let (mut click_sender, click_receiver) = unbounded();
-    let on_click = browser::closure_wrap(move || {
-        if let Err(err) = click_sender.start_send(()) {
-            error!("Could not send click event {:#?}", err);
-        };
-    });
-    elem.set_onclick(Some(on_click.as_ref().unchecked_ref()));
-    on_click.forget();
+// Mouse presses on an element, for as long as this is kept. Dropping it
+// takes the listener off the element again.
+pub struct MouseDownListener {
+    element: HtmlElement,
+    listener: Closure<dyn FnMut(web_sys::MouseEvent)>,
+    clicks: UnboundedReceiver<Point>,
+}
+
+impl MouseDownListener {
+    pub fn next_click(&mut self) -> Option<Point> {
+        self.clicks.try_next().ok().flatten()
+    }
+}
 
-    click_receiver
+impl Drop for MouseDownListener {
+    // Only clears the handler if it is still this one, in case another
+    // listener has been added since.
+    fn drop(&mut self) {
+        let listener: &JsValue = self.listener.as_ref();
+        if self
+            .element
+            .onmousedown()
+            .map_or(false, |current| JsValue::from(current) == *listener)
+        {
+            self.element.set_onmousedown(None);
+        }
+    }
+}
+
+pub fn add_mouse_down_handler(elem: HtmlElement) -> MouseDownListener {
+    let (mut mouse_down_sender, mouse_down_receiver) = unbounded();
+    let on_mouse_down: Closure<dyn FnMut(web_sys::MouseEvent)> =
+        browser::closure_wrap(move |event: web_sys::MouseEvent| {
+            let position = Point {
+                x: event.offset_x() as i16,
+                y: event.offset_y() as i16,
+            };
+            if let Err(err) = mouse_down_sender.start_send(position) {
+                error!("Could not send mouse down event {:#?}", err);
+            };
+        });
+    elem.set_onmousedown(Some(on_mouse_down.as_ref().unchecked_ref()));
+
+    MouseDownListener {
+        element: elem,
+        listener: on_mouse_down,
+        clicks: mouse_down_receiver,
+    }
 }
 
 unsafe fn draw_frame_rate(renderer: &Renderer, frame_time: f64) {
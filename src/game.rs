@@ -2,61 +2,173 @@ use std::rc::Rc;
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use futures::channel::mpsc::UnboundedReceiver;
-use rand::{thread_rng, Rng};
+use dog::Dog;
+use editing::Editing;
+use ghost::{GhostPlayback, GhostRecording, Ghosts};
+use ground::Ground;
+use practice::Practice;
+use race::Race;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 use rhb::RedHatBoy;
+use serde::Deserialize;
+use stats::RunStats;
+use title::Title;
+use toasts::Toasts;
+use tutorial::{Tutorial, TutorialScript};
 use web_sys::HtmlImageElement;
 
 use crate::{
-    browser,
-    engine::{self, Audio, Cell, Game, Image, KeyState, Point, Rect, Renderer, SpriteSheet},
-    segments::{platform_and_stone, stone_and_platform},
+    achievements::{Achievements, RunEvent},
+    browser::{
+        self,
+        ui::{Panel, UiEvent},
+    },
+    daily::{self, DailyScores},
+    difficulty::DifficultyDirector,
+    editor::SegmentDraft,
+    engine::{self, Audio, Cell, Game, Image, KeyState, Point, Rect, Renderer, Sound, SpriteSheet},
+    high_scores::HighScores,
+    segments::{
+        CeilingKind, CollectibleKind, Pit, PlatformPath, SegmentDefinition, SegmentSelector,
+        SegmentSet,
+    },
+    settings::{KeyBindings, Settings},
+    tiled::{GoalFlag, Level},
 };
 
+pub use rhb::{jump_offset, Abilities, Ability, BoyEvent};
+
+mod dog;
+mod editing;
+mod ghost;
+mod ground;
+mod practice;
+mod race;
 mod rhb;
+#[cfg(test)]
+mod solvability;
+mod stats;
+mod title;
+mod toasts;
+mod tutorial;
 
 const HEIGHT: i16 = 600;
+pub const GROUND: i16 = 550;
+const GROUND_START: i16 = -200;
 const TIMELINE_MINIMUM: i16 = 1000;
-const OBSTACLE_BUFFER: i16 = 20;
+const LIVES_POSITION: Point = Point { x: 20, y: 40 };
+const SCORE_POSITION: Point = Point { x: 20, y: 75 };
+const DAILY_POSITION: Point = Point { x: 20, y: 110 };
+const DISTANCE_PER_POINT: i32 = 100;
+const COLLECTIBLE_FRAMES: u8 = 23;
+const ROPE_COLOR: &str = "rgb(90, 60, 30)";
+const FPS_POSITION: Point = Point { x: 480, y: 40 };
+const DEBUG_POSITION: Point = Point { x: 400, y: 130 };
+const DEBUG_LINE_HEIGHT: i16 = 30;
 
 pub struct WalkTheDog {
     machine: Option<WalkTheDogStateMachine>,
+    frame_rate: FrameRate,
+}
+
+// Smooths the time between draws into a steady frames per second reading.
+#[derive(Default)]
+struct FrameRate {
+    last_draw: std::cell::Cell<f64>,
+    fps: std::cell::Cell<f64>,
+}
+
+impl FrameRate {
+    fn tick(&self, now: f64) -> f64 {
+        let elapsed = now - self.last_draw.replace(now);
+        if elapsed > 0.0 {
+            self.fps.set(self.fps.get() * 0.9 + 1000.0 / elapsed * 0.1);
+        }
+        self.fps.get()
+    }
 }
 
 enum WalkTheDogStateMachine {
+    Title(WalkTheDogState<Title>),
     Ready(WalkTheDogState<Ready>),
+    Editing(WalkTheDogState<Editing>),
     Walking(WalkTheDogState<Walking>),
     GameOver(WalkTheDogState<GameOver>),
+    Race(WalkTheDogState<Race>),
+    Tutorial(WalkTheDogState<Tutorial>),
+    Practice(WalkTheDogState<Practice>),
 }
 
 impl WalkTheDogStateMachine {
     fn new(walk: Walk) -> Self {
-        WalkTheDogStateMachine::Ready(WalkTheDogState::new(walk))
+        WalkTheDogStateMachine::Title(WalkTheDogState::<Title>::new(walk))
     }
 
     fn update(self, keystate: &KeyState) -> Self {
         match self {
+            WalkTheDogStateMachine::Title(state) => state.update(keystate).into(),
             WalkTheDogStateMachine::Ready(state) => state.update(keystate).into(),
+            WalkTheDogStateMachine::Editing(state) => state.update(keystate).into(),
             WalkTheDogStateMachine::Walking(state) => state.update(keystate).into(),
             WalkTheDogStateMachine::GameOver(state) => state.update().into(),
+            WalkTheDogStateMachine::Race(state) => state.update(keystate).into(),
+            WalkTheDogStateMachine::Tutorial(state) => state.update(keystate).into(),
+            WalkTheDogStateMachine::Practice(state) => state.update(keystate).into(),
+        }
+    }
+
+    fn walk(&self) -> &Walk {
+        match self {
+            WalkTheDogStateMachine::Title(state) => &state.walk,
+            WalkTheDogStateMachine::Ready(state) => &state.walk,
+            WalkTheDogStateMachine::Editing(state) => &state.walk,
+            WalkTheDogStateMachine::Walking(state) => &state.walk,
+            WalkTheDogStateMachine::GameOver(state) => &state.walk,
+            WalkTheDogStateMachine::Race(state) => &state.walk,
+            WalkTheDogStateMachine::Tutorial(state) => &state.walk,
+            WalkTheDogStateMachine::Practice(state) => &state.walk,
+        }
+    }
+
+    fn time_scale(&self) -> f32 {
+        match self {
+            WalkTheDogStateMachine::Practice(state) => state.time_scale(),
+            _ => 1.0,
         }
     }
 
     fn draw(&self, renderer: &Renderer) {
         match self {
+            WalkTheDogStateMachine::Title(state) => state.draw_title(renderer),
             WalkTheDogStateMachine::Ready(state) => state.draw(renderer),
-            WalkTheDogStateMachine::Walking(state) => state.draw(renderer),
+            WalkTheDogStateMachine::Editing(state) => state.draw_editor(renderer),
+            WalkTheDogStateMachine::Walking(state) => state.draw_walking(renderer),
             WalkTheDogStateMachine::GameOver(state) => state.draw(renderer),
+            WalkTheDogStateMachine::Race(state) => state.draw_race(renderer),
+            WalkTheDogStateMachine::Tutorial(state) => state.draw_tutorial(renderer),
+            WalkTheDogStateMachine::Practice(state) => state.draw_practice(renderer),
         }
     }
 }
 
+impl From<WalkTheDogState<Title>> for WalkTheDogStateMachine {
+    fn from(state: WalkTheDogState<Title>) -> Self {
+        WalkTheDogStateMachine::Title(state)
+    }
+}
+
 impl From<WalkTheDogState<Ready>> for WalkTheDogStateMachine {
     fn from(state: WalkTheDogState<Ready>) -> Self {
         WalkTheDogStateMachine::Ready(state)
     }
 }
 
+impl From<WalkTheDogState<Editing>> for WalkTheDogStateMachine {
+    fn from(state: WalkTheDogState<Editing>) -> Self {
+        WalkTheDogStateMachine::Editing(state)
+    }
+}
+
 impl From<WalkTheDogState<Walking>> for WalkTheDogStateMachine {
     fn from(state: WalkTheDogState<Walking>) -> Self {
         WalkTheDogStateMachine::Walking(state)
@@ -69,10 +181,29 @@ impl From<WalkTheDogState<GameOver>> for WalkTheDogStateMachine {
     }
 }
 
+impl From<WalkTheDogState<Race>> for WalkTheDogStateMachine {
+    fn from(state: WalkTheDogState<Race>) -> Self {
+        WalkTheDogStateMachine::Race(state)
+    }
+}
+
+impl From<WalkTheDogState<Tutorial>> for WalkTheDogStateMachine {
+    fn from(state: WalkTheDogState<Tutorial>) -> Self {
+        WalkTheDogStateMachine::Tutorial(state)
+    }
+}
+
+impl From<WalkTheDogState<Practice>> for WalkTheDogStateMachine {
+    fn from(state: WalkTheDogState<Practice>) -> Self {
+        WalkTheDogStateMachine::Practice(state)
+    }
+}
+
 impl From<ReadyEndState> for WalkTheDogStateMachine {
     fn from(state: ReadyEndState) -> Self {
         match state {
             ReadyEndState::Complete(walking) => walking.into(),
+            ReadyEndState::Edit(editing) => editing.into(),
             ReadyEndState::Continue(ready) => ready.into(),
         }
     }
@@ -86,6 +217,7 @@ impl<T> WalkTheDogState<T> {
 
 enum ReadyEndState {
     Complete(WalkTheDogState<Walking>),
+    Edit(WalkTheDogState<Editing>),
     Continue(WalkTheDogState<Ready>),
 }
 
@@ -93,14 +225,17 @@ impl WalkTheDogState<Ready> {
     fn new(walk: Walk) -> WalkTheDogState<Ready> {
         WalkTheDogState {
             _state: Ready,
-            walk,
+            walk: Box::new(walk),
         }
     }
 
     fn update(mut self, keystate: &KeyState) -> ReadyEndState {
         self.walk.boy.update();
-        if keystate.is_pressed("ArrowRight") {
+        self.walk.dog.update(0, self.walk.boy.bounding_box().x());
+        if keystate.is_pressed(&self.walk.settings.keys.run) {
             ReadyEndState::Complete(self.start_running())
+        } else if keystate.is_just_pressed("KeyE") {
+            ReadyEndState::Edit(self.edit())
         } else {
             ReadyEndState::Continue(self)
         }
@@ -116,6 +251,7 @@ impl WalkTheDogState<Ready> {
 
     fn run_right(&mut self) {
         self.walk.boy.run_right();
+        self.walk.dog.run_right();
     }
 }
 
@@ -134,50 +270,47 @@ impl From<WalkingEndState> for WalkTheDogStateMachine {
 }
 
 impl WalkTheDogState<Walking> {
-    fn end_game(self) -> WalkTheDogState<GameOver> {
-        let receiver = browser::draw_ui("<button id='new_game'>New Game</button>")
-            .and_then(|_unit| browser::find_html_element_by_id("new_game"))
-            .map(engine::add_click_handler)
+    fn draw_walking(&self, renderer: &Renderer) {
+        self.walk.draw(renderer);
+        self.walk.run.toasts.draw(renderer);
+    }
+
+    fn end_game(mut self) -> WalkTheDogState<GameOver> {
+        let score = self.walk.score();
+        let (previous_best, note) = self.walk.record_score(score);
+        self.walk.save_ghost(score);
+        self.walk.achievements.finish_run();
+        self.walk.achievements.save();
+        let results = self
+            .walk
+            .run
+            .stats
+            .results(self.walk.distance, score, previous_best);
+        let panel = Panel::new()
+            .and_then(|mut panel| {
+                if self.walk.reached_goal() {
+                    panel.label("Level Complete!")?;
+                }
+                if let Some(note) = &note {
+                    panel.label(note)?;
+                }
+                results.iter().try_for_each(|line| panel.label(line))?;
+                panel.button(GameOverAction::Retry, "Retry same seed")?;
+                panel.button(GameOverAction::NewCourse, "New course")?;
+                panel.button(GameOverAction::Menu, "Menu")?;
+                Ok(panel)
+            })
             .unwrap();
         WalkTheDogState {
-            _state: GameOver {
-                new_game_event: receiver,
-            },
+            _state: GameOver { panel },
             walk: self.walk,
         }
     }
 
     fn update(mut self, keystate: &KeyState) -> WalkingEndState {
-        if keystate.is_pressed("ArrowDown") {
-            self.walk.boy.slide();
-        }
-
-        if keystate.is_pressed("Space") {
-            self.walk.boy.jump();
-        }
-
-        self.walk.boy.update();
-        let walking_speed = self.walk.velocity();
-        let [first_background, second_background] = &mut self.walk.backgrounds;
-        first_background.move_horizontally(walking_speed);
-        second_background.move_horizontally(walking_speed);
-        if first_background.right() < 0 {
-            first_background.set_x(second_background.right());
-        }
-        if second_background.right() < 0 {
-            second_background.set_x(first_background.right());
-        }
-        self.walk.obstacles.retain(|obstacle| obstacle.right() > 0);
-        self.walk.obstacles.iter_mut().for_each(|obstacle| {
-            obstacle.move_horizontally(walking_speed);
-            obstacle.check_intersection(&mut self.walk.boy);
-        });
-        if self.walk.timeline < TIMELINE_MINIMUM {
-            self.walk.generate_next_segment();
-        } else {
-            self.walk.timeline += walking_speed;
-        }
-        if self.walk.knocked_out() {
+        let keys = self.walk.settings.keys.clone();
+        self.walk.update(keystate, &keys);
+        if self.walk.knocked_out() || self.walk.fell_into_pit() || self.walk.reached_goal() {
             WalkingEndState::Complete(self.end_game())
         } else {
             WalkingEndState::Continue(self)
@@ -188,6 +321,7 @@ impl WalkTheDogState<Walking> {
 enum GameOverEndState {
     Complete(WalkTheDogState<Ready>),
     Continue(WalkTheDogState<GameOver>),
+    Menu(WalkTheDogState<Title>),
 }
 
 impl From<GameOverEndState> for WalkTheDogStateMachine {
@@ -195,50 +329,61 @@ impl From<GameOverEndState> for WalkTheDogStateMachine {
         match state {
             GameOverEndState::Complete(ready) => ready.into(),
             GameOverEndState::Continue(game_over) => game_over.into(),
+            GameOverEndState::Menu(title) => title.into(),
         }
     }
 }
 
 impl WalkTheDogState<GameOver> {
     fn update(mut self) -> GameOverEndState {
-        if self._state.new_game_pressed() {
-            GameOverEndState::Complete(self.new_game())
-        } else {
-            GameOverEndState::Continue(self)
+        match self._state.panel.next_event() {
+            Some(UiEvent::Clicked(GameOverAction::Retry)) => {
+                GameOverEndState::Complete(self.retry())
+            }
+            Some(UiEvent::Clicked(GameOverAction::NewCourse)) => {
+                GameOverEndState::Complete(self.new_game())
+            }
+            Some(UiEvent::Clicked(GameOverAction::Menu)) => {
+                GameOverEndState::Menu(WalkTheDogState::<Title>::new(*self.walk))
+            }
+            _ => GameOverEndState::Continue(self),
         }
     }
 
+    // Dropping the game over state takes its panel off the page.
     fn new_game(self) -> WalkTheDogState<Ready> {
-        if let Err(err) = browser::hide_ui() {
-            error!("Error hiding the UI {:#?}", err);
-        };
-        WalkTheDogState {
-            _state: Ready,
-            walk: Walk::reset(self.walk),
-        }
+        WalkTheDogState::<Ready>::new(Walk::reset(*self.walk))
     }
-}
 
-impl GameOver {
-    fn new_game_pressed(&mut self) -> bool {
-        matches!(self.new_game_event.try_next(), Ok(Some(())))
+    fn retry(self) -> WalkTheDogState<Ready> {
+        WalkTheDogState::<Ready>::new(Walk::retry(*self.walk))
     }
 }
 
 struct WalkTheDogState<T> {
     _state: T,
-    walk: Walk,
+    walk: Box<Walk>,
 }
 
 struct Ready;
 struct Walking;
 struct GameOver {
-    new_game_event: UnboundedReceiver<()>,
+    panel: Panel<GameOverAction>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum GameOverAction {
+    Retry,
+    NewCourse,
+    Menu,
 }
 
 impl WalkTheDog {
     pub fn new() -> Self {
-        WalkTheDog { machine: None }
+        WalkTheDog {
+            machine: None,
+            frame_rate: FrameRate::default(),
+        }
     }
 }
 
@@ -249,27 +394,57 @@ impl Game for WalkTheDog {
             None => {
                 let audio = Audio::new()?;
                 let sound = audio.load_sound("SFX_Jump_23.mp3").await?;
+                let double_jump_sound = audio.load_sound("SFX_Double_Jump.wav").await?;
+                let pickup_sound = audio.load_sound("SFX_Pickup.wav").await?;
                 let background_music = audio.load_sound("background_song.mp3").await?;
-                audio.play_looping_sound(&background_music)?;
+                let collectible_assets = Rc::new(CollectibleAssets {
+                    sheet: SpriteSheet::new(
+                        browser::fetch_json("collectibles.json").await?.try_into()?,
+                        engine::load_image("collectibles.png").await?,
+                    ),
+                    audio: audio.clone(),
+                    sound: pickup_sound,
+                });
                 let rhb = RedHatBoy::new(
                     browser::fetch_json("rhb.json").await?.try_into()?,
                     engine::load_image("rhb.png").await?,
-                    audio,
+                    audio.clone(),
                     sound,
+                    double_jump_sound,
+                    Abilities::default(),
+                );
+                let dog = Dog::new(
+                    browser::fetch_json("dog.json").await?.try_into()?,
+                    engine::load_image("dog.png").await?,
                 );
                 let background = engine::load_image("BG.png").await?;
                 let stone = engine::load_image("Stone.png").await?;
+                let difficulty = DifficultyDirector::new(
+                    browser::fetch_json("difficulty.json").await?.try_into()?,
+                );
                 let tiles = browser::fetch_json("tiles.json").await?;
                 let sprite_sheet = Rc::new(SpriteSheet::new(
                     tiles.try_into()?,
                     engine::load_image("tiles.png").await?,
                 ));
+                let ceiling_sheet = Rc::new(SpriteSheet::new(
+                    browser::fetch_json("overhead.json").await?.try_into()?,
+                    engine::load_image("overhead.png").await?,
+                ));
+                let segments: Rc<SegmentSet> =
+                    Rc::new(browser::fetch_json("segments.json").await?.try_into()?);
+                let level: Option<Rc<Level>> = match browser::query_parameter("level")? {
+                    Some(name) => Some(Rc::new(
+                        browser::fetch_json(&format!("levels/{}.json", name))
+                            .await?
+                            .try_into()?,
+                    )),
+                    None => None,
+                };
                 let background_width = background.width() as i16;
-                let starting_obstacles =
-                    stone_and_platform(stone.clone(), Rc::clone(&sprite_sheet), 0);
-                let timeline = rightmost(&starting_obstacles);
-                let machine = WalkTheDogStateMachine::new(Walk {
+                let mut walk = Walk {
                     boy: rhb,
+                    dog,
                     backgrounds: [
                         Image::new(background.clone(), Point { x: 0, y: 0 }),
                         Image::new(
@@ -280,14 +455,43 @@ impl Game for WalkTheDog {
                             },
                         ),
                     ],
-                    obstacles: starting_obstacles,
+                    ground: Ground::default(),
+                    obstacles: vec![],
                     obstacle_sheet: sprite_sheet,
+                    collectible_assets,
+                    ceiling_sheet,
                     stone,
-                    timeline,
-                });
+                    selector: SegmentSelector::default(),
+                    segments,
+                    level,
+                    course: Course::Random,
+                    tutorial: Rc::new(browser::fetch_json("tutorial.json").await?.try_into()?),
+                    goal: None,
+                    timeline: 0,
+                    distance: 0,
+                    seed: 0,
+                    rng: StdRng::seed_from_u64(0),
+                    daily: None,
+                    run: RunState::default(),
+                    ghosts: Ghosts::load(),
+                    difficulty,
+                    draft: SegmentDraft::default(),
+                    high_scores: HighScores::load(),
+                    daily_scores: DailyScores::load(),
+                    achievements: Achievements::load(
+                        browser::fetch_json("achievements.json").await?.try_into()?,
+                    ),
+                    settings: Settings::load(),
+                    audio,
+                    background_music,
+                    music_started: false,
+                };
+                walk.apply_settings();
+                let machine = WalkTheDogStateMachine::new(walk);
 
                 Ok(Box::new(WalkTheDog {
                     machine: Some(machine),
+                    frame_rate: FrameRate::default(),
                 }))
             }
             Some(_) => Err(anyhow!("Game initialised more than once")),
@@ -301,111 +505,604 @@ impl Game for WalkTheDog {
         assert!(self.machine.is_some());
     }
 
+    fn time_scale(&self) -> f32 {
+        self.machine
+            .as_ref()
+            .map_or(1.0, WalkTheDogStateMachine::time_scale)
+    }
+
     fn draw(&self, renderer: &Renderer) {
         renderer.clear(&Rect::new(Point { x: 0, y: 0 }, 600, HEIGHT));
 
         if let Some(machine) = &self.machine {
             machine.draw(renderer);
+            let fps = browser::now().map(|now| self.frame_rate.tick(now));
+            if let (true, Ok(fps)) = (machine.walk().settings.show_fps, fps) {
+                if let Err(err) = renderer.draw_text(&format!("FPS {:.0}", fps), &FPS_POSITION) {
+                    error!("Could not draw FPS {:#?}", err);
+                }
+            }
         }
     }
 }
 
+// Where a walk's segments come from once the first has been laid out.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Course {
+    // Picked at random, as hard as the difficulty allows.
+    Random,
+    // The named segment over and over, for practice.
+    Repeating(String),
+    // Placed by whatever is running the walk, such as the tutorial.
+    Scripted,
+}
+
+// What a single run builds up, which starts again with every run.
+#[derive(Default)]
+struct RunState {
+    stats: RunStats,
+    toasts: Toasts,
+    // The best run so far on this course, raced alongside the boy.
+    ghost: Option<GhostPlayback>,
+    // Only runs on a seeded course are recorded, since a ghost is only
+    // worth anything on the same course again.
+    recording: Option<GhostRecording>,
+}
+
 pub struct Walk {
     boy: RedHatBoy,
+    dog: Dog,
     backgrounds: [Image; 2],
+    ground: Ground,
     obstacles: Vec<Box<dyn Obstacle>>,
     obstacle_sheet: Rc<SpriteSheet>,
+    collectible_assets: Rc<CollectibleAssets>,
+    ceiling_sheet: Rc<SpriteSheet>,
     stone: HtmlImageElement,
+    segments: Rc<SegmentSet>,
+    selector: SegmentSelector,
+    level: Option<Rc<Level>>,
+    course: Course,
+    tutorial: Rc<TutorialScript>,
+    goal: Option<Goal>,
     timeline: i16,
+    distance: i32,
+    // Segments are chosen from a generator seeded at the start of each run,
+    // so that a run can be tried again on the same course.
+    seed: u64,
+    rng: StdRng,
+    // The date of the daily challenge being run, if it is one.
+    daily: Option<String>,
+    run: RunState,
+    ghosts: Ghosts,
+    difficulty: DifficultyDirector,
+    draft: SegmentDraft,
+    high_scores: HighScores,
+    daily_scores: DailyScores,
+    achievements: Achievements,
+    settings: Settings,
+    audio: Audio,
+    background_music: Sound,
+    // Set once the music is playing, so going back to the title and
+    // starting another run doesn't play it a second time over the first.
+    music_started: bool,
 }
 
 impl Walk {
     fn reset(walk: Self) -> Self {
-        let starting_obstacles =
-            stone_and_platform(walk.stone.clone(), walk.obstacle_sheet.clone(), 0);
-        let timeline = rightmost(&starting_obstacles);
+        Walk::reset_with_seed(walk, thread_rng().gen(), None)
+    }
+
+    fn retry(walk: Self) -> Self {
+        let seed = walk.seed;
+        let daily = walk.daily.clone();
+        Walk::reset_with_seed(walk, seed, daily)
+    }
+
+    // The course for the given day, which is the same for everyone.
+    fn daily(walk: Self, date: String) -> Self {
+        Walk::reset_with_seed(walk, daily::seed(&date), Some(date))
+    }
+
+    // One segment over and over, already running, with the boy able to do
+    // whatever it asks of him.
+    fn practice(walk: Self, segment: &SegmentDefinition) -> Self {
+        let mut walk = Walk {
+            course: Course::Repeating(segment.name.clone()),
+            ..Walk::reset_with_segment(walk, segment)
+        };
+        if let Some(ability) = segment.requires {
+            walk.boy.unlock(ability);
+        }
+        walk.boy.run_right();
+        walk.dog.run_right();
+        walk
+    }
+
+    // Both walks in a race are on the same course, with no ghost to race
+    // besides each other.
+    fn race(walk: Self, seed: u64) -> Self {
+        Walk {
+            run: RunState::default(),
+            ..Walk::reset_with_seed(walk, seed, None)
+        }
+    }
+
+    // A second walk sharing this one's assets, for the other player in a
+    // race. It starts with none of the saved scores or progress, and nothing
+    // it does is kept.
+    fn rival(&self) -> Self {
+        Walk {
+            boy: self.boy.clone(),
+            dog: self.dog.clone(),
+            backgrounds: self.backgrounds.clone(),
+            ground: Ground::default(),
+            obstacles: vec![],
+            obstacle_sheet: Rc::clone(&self.obstacle_sheet),
+            collectible_assets: Rc::clone(&self.collectible_assets),
+            ceiling_sheet: Rc::clone(&self.ceiling_sheet),
+            stone: self.stone.clone(),
+            segments: Rc::clone(&self.segments),
+            selector: SegmentSelector::default(),
+            level: self.level.clone(),
+            course: Course::Random,
+            tutorial: Rc::clone(&self.tutorial),
+            goal: None,
+            timeline: 0,
+            distance: 0,
+            seed: self.seed,
+            rng: StdRng::seed_from_u64(self.seed),
+            daily: None,
+            run: RunState::default(),
+            ghosts: Ghosts::default(),
+            difficulty: self.difficulty.clone(),
+            draft: SegmentDraft::default(),
+            high_scores: HighScores::default(),
+            daily_scores: DailyScores::default(),
+            achievements: Achievements::default(),
+            settings: self.settings.clone(),
+            audio: self.audio.clone(),
+            background_music: self.background_music.clone(),
+            music_started: false,
+        }
+    }
+
+    fn reset_with_seed(walk: Self, seed: u64, daily: Option<String>) -> Self {
+        let walk = match walk.level.clone() {
+            Some(level) => Walk::reset_with_level(walk, &level),
+            None => {
+                let segments = Rc::clone(&walk.segments);
+                Walk::reset_with_segment(walk, segments.starting_segment())
+            }
+        };
+        Walk {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            daily,
+            run: RunState {
+                ghost: walk.ghosts.find(seed).map(GhostPlayback::new),
+                recording: Some(GhostRecording::default()),
+                ..RunState::default()
+            },
+            ..walk
+        }
+    }
+
+    fn reset_with_level(walk: Self, level: &Level) -> Self {
+        let mut ground = Ground::new(GROUND_START);
+        dig_pits(&mut ground, &level.pits, 0);
         Walk {
             boy: RedHatBoy::reset(walk.boy),
-            backgrounds: walk.backgrounds,
+            dog: Dog::reset(walk.dog),
+            ground,
+            obstacles: level.create(&walk.stone, &walk.obstacle_sheet),
+            goal: Some(Goal::new(Rc::clone(&walk.obstacle_sheet), &level.goal)),
+            course: Course::Random,
+            timeline: 0,
+            distance: 0,
+            daily: None,
+            run: RunState::default(),
+            difficulty: DifficultyDirector::reset(walk.difficulty),
+            ..walk
+        }
+        .with_starting_lives()
+    }
+
+    fn reset_with_segment(walk: Self, starting_segment: &SegmentDefinition) -> Self {
+        let starting_obstacles = walk.segments.create(
+            starting_segment,
+            &walk.stone,
+            &walk.obstacle_sheet,
+            &walk.collectible_assets,
+            &walk.ceiling_sheet,
+            0,
+        );
+        let mut ground = Ground::new(GROUND_START);
+        let timeline =
+            rightmost(&starting_obstacles).max(dig_pits(&mut ground, &starting_segment.pits, 0));
+        Walk {
+            boy: RedHatBoy::reset(walk.boy),
+            dog: Dog::reset(walk.dog),
+            ground,
             obstacles: starting_obstacles,
-            obstacle_sheet: walk.obstacle_sheet,
-            stone: walk.stone,
+            selector: SegmentSelector::starting_with(starting_segment),
+            course: Course::Random,
+            goal: None,
             timeline,
+            distance: 0,
+            daily: None,
+            run: RunState::default(),
+            difficulty: DifficultyDirector::reset(walk.difficulty),
+            ..walk
+        }
+        .with_starting_lives()
+    }
+
+    // Puts the score in the table for the kind of run it was, returning the
+    // best before it and a note on how it was recorded if that needs saying.
+    fn record_score(&mut self, score: u32) -> (Option<u32>, Option<String>) {
+        match &self.daily {
+            Some(date) => {
+                let previous_best = self.daily_scores.score(date);
+                let note = if self.daily_scores.record(date, score) {
+                    self.daily_scores.save();
+                    format!("Daily challenge {} recorded", date)
+                } else {
+                    format!("Practice run, {} is already recorded", date)
+                };
+                (previous_best, Some(note))
+            }
+            None => {
+                let previous_best = self.high_scores.scores().first().copied();
+                if self.high_scores.record(score).is_some() {
+                    self.high_scores.save();
+                }
+                (previous_best, None)
+            }
+        }
+    }
+
+    fn record_ghost(&mut self) {
+        if let Some(recording) = self.run.recording.as_mut() {
+            recording.record(
+                self.distance,
+                self.boy.destination_box().position,
+                self.boy.sprite_name(),
+            );
+        }
+        if let Some(ghost) = self.run.ghost.as_mut() {
+            ghost.update();
+        }
+    }
+
+    fn save_ghost(&mut self, score: u32) {
+        if let Some(recording) = self.run.recording.take() {
+            let ghost = recording.finish(self.seed, score);
+            if self.ghosts.offer(ghost, self.daily.is_some()) {
+                self.ghosts.save();
+            }
+        }
+    }
+
+    // Moves the run on by one update, with the boy controlled by `keys`.
+    fn update(&mut self, keystate: &KeyState, keys: &KeyBindings) {
+        // Air moves are sent before their ground equivalents so a single
+        // press can't both take off and use an air move in the same update.
+        if keystate.is_just_pressed(&keys.slide) {
+            self.boy.air_action();
+        }
+
+        if keystate.is_pressed(&keys.slide) {
+            self.boy.slide();
+        }
+
+        let takeoff = self.boy.bounding_box().x();
+        if keystate.is_just_pressed(&keys.jump) && self.boy.double_jump() {
+            self.dog.follow_jump(takeoff, true);
+        }
+
+        if keystate.is_pressed(&keys.jump) && self.boy.jump() {
+            self.dog.follow_jump(takeoff, false);
+        }
+
+        let over_ground = self.ground.supports(&self.boy.bounding_box());
+        self.boy.set_over_ground(over_ground);
+        self.boy.update();
+        let walking_speed = self.velocity();
+        let dog_over_ground = self.ground.supports(&self.dog.bounding_box());
+        self.dog.set_over_ground(dog_over_ground);
+        self.dog.update(walking_speed, self.boy.bounding_box().x());
+        self.distance -= i32::from(walking_speed);
+        self.apply_difficulty();
+        self.scroll_backgrounds(walking_speed);
+        self.ground.move_horizontally(walking_speed);
+        self.ground.extend_to(self.timeline.max(TIMELINE_MINIMUM));
+        self.clear_passed_obstacles();
+        self.obstacles.iter_mut().for_each(|obstacle| {
+            obstacle.move_horizontally(walking_speed);
+            obstacle.check_intersection(&mut self.boy);
+            obstacle.update(&mut self.boy);
+            obstacle.check_dog_intersection(&mut self.dog);
+        });
+        self.record_boy_events();
+        self.record_ghost();
+        self.run.stats.tick();
+        self.achieve(RunEvent::Ran(self.distance));
+        self.run.toasts.update();
+        self.dog.pull(self.boy.hand());
+        match self.goal.as_mut() {
+            Some(goal) => goal.move_horizontally(walking_speed),
+            None if self.timeline < TIMELINE_MINIMUM => self.generate_next_segment(),
+            None => self.timeline += walking_speed,
+        }
+    }
+
+    fn with_starting_lives(mut self) -> Self {
+        self.boy.set_lives(self.settings.difficulty.lives());
+        self
+    }
+
+    // Puts the player's settings into effect, both at startup and after
+    // they are changed.
+    fn apply_settings(&mut self) {
+        self.audio
+            .set_volumes(self.settings.music_gain(), self.settings.effects_gain());
+        self.boy.set_reduced_motion(self.settings.reduced_motion);
+    }
+
+    // An empty stretch of ground for the boy and his dog to run along behind
+    // the title screen. With reduced motion they wait there instead.
+    fn attract(walk: Self) -> Self {
+        let mut walk = Walk {
+            boy: RedHatBoy::reset(walk.boy),
+            dog: Dog::reset(walk.dog),
+            ground: Ground::new(GROUND_START),
+            obstacles: vec![],
+            goal: None,
+            timeline: 0,
+            distance: 0,
+            run: RunState::default(),
+            ..walk
+        };
+        if !walk.settings.reduced_motion {
+            walk.boy.run_right();
+            walk.dog.run_right();
+        }
+        walk
+    }
+
+    fn run_attract_mode(&mut self, jump: bool) {
+        let takeoff = self.boy.bounding_box().x();
+        if jump && self.boy.jump() {
+            self.dog.follow_jump(takeoff, false);
+        }
+        self.boy.update();
+        // Nothing done behind the menu counts towards a run.
+        self.boy.take_events();
+        let walking_speed = self.velocity();
+        self.scroll_backgrounds(walking_speed);
+        self.ground.move_horizontally(walking_speed);
+        self.ground.extend_to(TIMELINE_MINIMUM);
+        self.dog.update(walking_speed, self.boy.bounding_box().x());
+    }
+
+    fn start_music(&mut self) {
+        if self.music_started {
+            return;
+        }
+        match self
+            .audio
+            .resume()
+            .and_then(|_unit| self.audio.play_looping_sound(&self.background_music))
+        {
+            Ok(()) => self.music_started = true,
+            Err(err) => error!("Could not play background music {:#?}", err),
+        }
+    }
+
+    fn scroll_backgrounds(&mut self, walking_speed: i16) {
+        let [first_background, second_background] = &mut self.backgrounds;
+        first_background.move_horizontally(walking_speed);
+        second_background.move_horizontally(walking_speed);
+        if first_background.right() < 0 {
+            first_background.set_x(second_background.right());
+        }
+        if second_background.right() < 0 {
+            second_background.set_x(first_background.right());
         }
     }
 
+    fn record_boy_events(&mut self) {
+        self.boy.take_events().into_iter().for_each(|event| {
+            self.run.stats.record(event);
+            self.achieve(RunEvent::Boy(event));
+        });
+    }
+
+    // Counts the obstacles that have scrolled off behind the boy, which he
+    // got past, before dropping them.
+    fn clear_passed_obstacles(&mut self) {
+        let cleared: Vec<ObstacleKind> = self
+            .obstacles
+            .iter()
+            .filter(|obstacle| obstacle.right() <= 0)
+            .map(|obstacle| obstacle.kind())
+            .filter(|kind| *kind != ObstacleKind::Collectible)
+            .collect();
+        self.run.stats.clear_obstacles(cleared.len());
+        cleared
+            .into_iter()
+            .for_each(|kind| self.achieve(RunEvent::Cleared(kind)));
+        self.obstacles.retain(|obstacle| obstacle.right() > 0);
+    }
+
+    fn achieve(&mut self, event: RunEvent) {
+        let unlocked = self.achievements.record(&event);
+        if !unlocked.is_empty() {
+            self.achievements.save();
+        }
+        unlocked
+            .into_iter()
+            .for_each(|name| self.run.toasts.push(format!("Achievement unlocked: {}", name)));
+    }
+
     fn knocked_out(&self) -> bool {
         self.boy.knocked_out()
     }
 
+    fn fell_into_pit(&self) -> bool {
+        self.boy.pos_y() > HEIGHT
+    }
+
+    fn reached_goal(&self) -> bool {
+        self.goal
+            .as_ref()
+            .map_or(false, |goal| goal.reached_by(&self.boy))
+    }
+
+    // A point for every stretch of distance run plus the points of
+    // everything collected.
+    fn score(&self) -> u32 {
+        (self.distance.max(0) / DISTANCE_PER_POINT) as u32 + self.boy.collected()
+    }
+
     fn draw(&self, renderer: &Renderer) {
+        self.draw_scene(renderer);
+        if let Err(err) =
+            renderer.draw_text(&format!("Lives {}", self.boy.lives()), &LIVES_POSITION)
+        {
+            error!("Could not draw lives {:#?}", err);
+        }
+        if let Err(err) = renderer.draw_text(&format!("Score {}", self.score()), &SCORE_POSITION) {
+            error!("Could not draw score {:#?}", err);
+        }
+        if let Some(date) = &self.daily {
+            if let Err(err) = renderer.draw_text(&format!("Daily {}", date), &DAILY_POSITION) {
+                error!("Could not draw daily challenge date {:#?}", err);
+            }
+        }
+        if cfg!(debug_assertions) {
+            self.draw_debug(renderer);
+        }
+    }
+
+    fn draw_scene(&self, renderer: &Renderer) {
         self.backgrounds.iter().for_each(|background| {
             background.draw(renderer);
         });
+        self.ground.draw(renderer, &self.obstacle_sheet);
+        if let Some(ghost) = &self.run.ghost {
+            ghost.draw(renderer, &self.boy, self.distance);
+        }
+        self.dog.draw(renderer);
+        self.dog.draw_leash(renderer, self.boy.hand());
         self.boy.draw(renderer);
         self.obstacles.iter().for_each(|obstacle| {
             obstacle.draw(renderer);
-        })
+        });
+        if let Some(goal) = &self.goal {
+            goal.draw(renderer);
+        }
+    }
+
+    fn draw_debug(&self, renderer: &Renderer) {
+        let stage = self.difficulty.stage();
+        [
+            format!("Distance {}", self.distance),
+            format!("Stage {}", self.difficulty.stage_number()),
+            format!("Speed {}", stage.running_speed),
+            format!("Buffer {}", stage.obstacle_buffer),
+            format!("Max Segment {}", stage.max_segment_difficulty),
+        ]
+        .iter()
+        .zip(0..)
+        .for_each(|(line, index)| {
+            let position = Point {
+                x: DEBUG_POSITION.x,
+                y: DEBUG_POSITION.y + index * DEBUG_LINE_HEIGHT,
+            };
+            if let Err(err) = renderer.draw_text(line, &position) {
+                error!("Could not draw debug overlay {:#?}", err);
+            }
+        });
     }
 
     fn velocity(&self) -> i16 {
         -self.boy.walking_speed()
     }
 
+    fn apply_difficulty(&mut self) {
+        self.difficulty.update(self.distance);
+        self.boy
+            .set_running_speed(self.difficulty.stage().running_speed);
+        self.difficulty
+            .unlocked_abilities()
+            .for_each(|ability| self.boy.unlock(*ability));
+    }
+
     fn generate_next_segment(&mut self) {
-        let mut rng = thread_rng();
-        let next_segment = rng.gen_range(0..2);
-        let mut next_obstacles = match next_segment {
-            0 => stone_and_platform(
-                self.stone.clone(),
-                Rc::clone(&self.obstacle_sheet),
-                self.timeline + OBSTACLE_BUFFER,
-            ),
-            1 => platform_and_stone(
-                self.stone.clone(),
-                Rc::clone(&self.obstacle_sheet),
-                self.timeline + OBSTACLE_BUFFER,
+        let segments = Rc::clone(&self.segments);
+        let next_segment = match &self.course {
+            Course::Random => self.selector.next(
+                &segments,
+                self.difficulty.stage().max_segment_difficulty,
+                self.boy.abilities(),
+                &mut self.rng,
             ),
-            _ => vec![],
+            Course::Repeating(name) => segments.segment(name),
+            Course::Scripted => return,
         };
-        self.timeline = rightmost(&next_obstacles);
-        self.obstacles.append(&mut next_obstacles);
+        // The constraints can rule every segment out, but the boy should
+        // never run on into nothing.
+        let segment = next_segment.unwrap_or_else(|| {
+            error!("No segment can follow, so starting the course again");
+            segments.starting_segment()
+        });
+        self.place_segment(segment);
+        self.achieve(RunEvent::Segment(segment.name.clone()));
+    }
+
+    // Lays a segment out after the last one, returning the x it starts at.
+    fn place_segment(&mut self, segment: &SegmentDefinition) -> i16 {
+        let offset_x = self.timeline + self.difficulty.stage().obstacle_buffer;
+        let mut obstacles = self.segments.create(
+            segment,
+            &self.stone,
+            &self.obstacle_sheet,
+            &self.collectible_assets,
+            &self.ceiling_sheet,
+            offset_x,
+        );
+        let pits_end = dig_pits(&mut self.ground, &segment.pits, offset_x);
+        self.timeline = rightmost(&obstacles).max(pits_end);
+        self.obstacles.append(&mut obstacles);
+        offset_x
     }
 }
 
+// A block of tiles laid out in rows from the top left, colliding with a
+// box the size of each tile.
 pub struct Platform {
     sheet: Rc<SpriteSheet>,
     bounding_boxes: Vec<Rect>,
-    sprites: Vec<Cell>,
+    tiles: Vec<(Cell, Point)>,
     position: Point,
 }
 
 impl Platform {
-    pub fn new(
-        sheet: Rc<SpriteSheet>,
-        position: Point,
-        sprite_names: &[&str],
-        bounding_boxes: &[Rect],
-    ) -> Self {
-        let sprites = sprite_names
-            .iter()
-            .filter_map(|sprite_name| sheet.cell(sprite_name).cloned())
+    pub fn new(sheet: Rc<SpriteSheet>, position: Point, rows: &[Vec<&str>]) -> Self {
+        let tiles: Vec<(Cell, Point)> = lay_out_tiles(rows, |sprite_name| sheet.cell(sprite_name))
+            .into_iter()
+            .map(|(cell, offset)| (cell.clone(), offset))
             .collect();
-        let bounding_boxes = bounding_boxes
+        let bounding_boxes = tiles
             .iter()
-            .map(|bounding_box| {
-                Rect::new_from_x_y(
-                    bounding_box.x() + position.x,
-                    bounding_box.y() + position.y,
-                    bounding_box.width,
-                    bounding_box.height,
-                )
-            })
+            .map(|(cell, offset)| tile_bounding_box(cell, position, *offset))
             .collect();
         Platform {
             sheet,
             position,
-            sprites,
+            tiles,
             bounding_boxes,
         }
     }
@@ -413,6 +1110,46 @@ impl Platform {
     fn bounding_boxes(&self) -> &Vec<Rect> {
         &self.bounding_boxes
     }
+
+    fn move_by(&mut self, delta: Point) {
+        self.position.x += delta.x;
+        self.position.y += delta.y;
+        self.bounding_boxes.iter_mut().for_each(|bounding_box| {
+            bounding_box.set_x(bounding_box.x() + delta.x);
+            bounding_box.set_y(bounding_box.y() + delta.y);
+        });
+    }
+}
+
+// Places each row of sprites after the one above it, left to right, and
+// returns every cell found with its offset from the top left. A row is as
+// tall as its tallest cell.
+pub fn lay_out_tiles<'a>(
+    rows: &[Vec<&str>],
+    cell: impl Fn(&str) -> Option<&'a Cell>,
+) -> Vec<(&'a Cell, Point)> {
+    let mut tiles = vec![];
+    let mut y = 0;
+    for row in rows {
+        let mut x = 0;
+        let mut row_height = 0;
+        for sprite in row.iter().filter_map(|sprite_name| cell(sprite_name)) {
+            tiles.push((sprite, Point { x, y }));
+            x += sprite.frame.w;
+            row_height = row_height.max(sprite.frame.h);
+        }
+        y += row_height;
+    }
+    tiles
+}
+
+pub fn tile_bounding_box(cell: &Cell, position: Point, offset: Point) -> Rect {
+    Rect::new_from_x_y(
+        position.x + offset.x,
+        position.y + offset.y,
+        cell.frame.w,
+        cell.frame.h,
+    )
 }
 
 pub trait Obstacle {
@@ -420,9 +1157,32 @@ pub trait Obstacle {
     fn draw(&self, renderer: &Renderer);
     fn move_horizontally(&mut self, x: i16);
     fn right(&self) -> i16;
+
+    // Called once a frame after collisions are checked, for obstacles that
+    // move on their own.
+    fn update(&mut self, _boy: &mut RedHatBoy) {}
+
+    // The dog runs into the same obstacles as the boy, but only stumbles
+    // where he would be hurt.
+    fn check_dog_intersection(&self, _dog: &mut Dog) {}
+
+    fn kind(&self) -> ObstacleKind;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ObstacleKind {
+    Stone,
+    Platform,
+    Ceiling,
+    Collectible,
 }
 
 impl Obstacle for Platform {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Platform
+    }
+
     fn check_intersection(&self, boy: &mut RedHatBoy) {
         if let Some(box_to_land_on) = self
             .bounding_boxes()
@@ -432,14 +1192,27 @@ impl Obstacle for Platform {
             if boy.velocity_y() > 0 && boy.pos_y() < self.position.y {
                 boy.land_on(box_to_land_on.y());
             } else {
-                boy.knock_out();
+                boy.hit();
+            }
+        }
+    }
+
+    fn check_dog_intersection(&self, dog: &mut Dog) {
+        if let Some(box_to_land_on) = self
+            .bounding_boxes()
+            .iter()
+            .find(|&bounding_box| dog.bounding_box().intersects(bounding_box))
+        {
+            if dog.velocity_y() > 0 && dog.pos_y() < self.position.y {
+                dog.land_on(box_to_land_on.y());
+            } else {
+                dog.stumble();
             }
         }
     }
 
     fn draw(&self, renderer: &Renderer) {
-        let mut x = 0;
-        self.sprites.iter().for_each(|sprite| {
+        self.tiles.iter().for_each(|(sprite, offset)| {
             self.sheet.draw(
                 renderer,
                 &Rect::new_from_x_y(
@@ -448,29 +1221,225 @@ impl Obstacle for Platform {
                     sprite.frame.w,
                     sprite.frame.h,
                 ),
+                &tile_bounding_box(sprite, self.position, *offset),
+            );
+        });
+    }
+
+    fn move_horizontally(&mut self, x: i16) {
+        self.move_by(Point { x, y: 0 });
+    }
+
+    fn right(&self) -> i16 {
+        self.bounding_boxes()
+            .iter()
+            .map(Rect::right)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// A platform that follows a path, carrying the boy with it while he stands
+// on top.
+pub struct MovingPlatform {
+    platform: Platform,
+    path: PlatformPath,
+    frame: u16,
+}
+
+impl MovingPlatform {
+    pub fn new(platform: Platform, path: PlatformPath) -> Self {
+        MovingPlatform {
+            platform,
+            path,
+            frame: 0,
+        }
+    }
+}
+
+impl Obstacle for MovingPlatform {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Platform
+    }
+
+    fn check_intersection(&self, boy: &mut RedHatBoy) {
+        self.platform.check_intersection(boy);
+    }
+
+    fn check_dog_intersection(&self, dog: &mut Dog) {
+        self.platform.check_dog_intersection(dog);
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        self.platform.draw(renderer);
+    }
+
+    fn move_horizontally(&mut self, x: i16) {
+        self.platform.move_horizontally(x);
+    }
+
+    fn right(&self) -> i16 {
+        self.platform.right()
+    }
+
+    fn update(&mut self, boy: &mut RedHatBoy) {
+        let previous = self.path.offset(self.frame);
+        self.frame = (self.frame + 1) % self.path.period.max(1);
+        let next = self.path.offset(self.frame);
+        let delta = Point {
+            x: next.x - previous.x,
+            y: next.y - previous.y,
+        };
+        if boy.standing_on(self.platform.position.y) {
+            boy.ride(delta);
+        }
+        self.platform.move_by(delta);
+    }
+}
+
+// The sprite sheet and pickup sound shared by every collectible.
+pub struct CollectibleAssets {
+    pub sheet: SpriteSheet,
+    pub audio: Audio,
+    pub sound: Sound,
+}
+
+// Something to pick up, which spins until the boy runs into it and is then
+// counted towards his score and hidden.
+pub struct Collectible {
+    assets: Rc<CollectibleAssets>,
+    kind: CollectibleKind,
+    bounding_box: Rect,
+    frame: u8,
+    collected: bool,
+}
+
+impl Collectible {
+    pub fn new(assets: Rc<CollectibleAssets>, kind: CollectibleKind, position: Point) -> Self {
+        let (width, height) = assets
+            .sheet
+            .cell(&format!("{} (1).png", kind.frame_name()))
+            .map_or((0, 0), |cell| (cell.frame.w, cell.frame.h));
+        Collectible {
+            assets,
+            kind,
+            bounding_box: Rect::new(position, width, height),
+            frame: 0,
+            collected: false,
+        }
+    }
+
+    fn sprite_name(&self) -> String {
+        format!("{} ({}).png", self.kind.frame_name(), (self.frame / 3) + 1)
+    }
+}
+
+impl Obstacle for Collectible {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Collectible
+    }
+
+    fn check_intersection(&self, _boy: &mut RedHatBoy) {}
+
+    fn draw(&self, renderer: &Renderer) {
+        if self.collected {
+            return;
+        }
+        if let Some(sprite) = self.assets.sheet.cell(&self.sprite_name()) {
+            self.assets.sheet.draw(
+                renderer,
                 &Rect::new_from_x_y(
-                    self.position.x + x,
-                    self.position.y,
+                    sprite.frame.x,
+                    sprite.frame.y,
                     sprite.frame.w,
                     sprite.frame.h,
                 ),
+                &self.bounding_box,
             );
-            x += sprite.frame.w;
-        });
+        }
     }
 
     fn move_horizontally(&mut self, x: i16) {
-        self.position.x += x;
-        self.bounding_boxes
-            .iter_mut()
-            .for_each(|bounding_box| bounding_box.set_x(bounding_box.position.x + x))
+        self.bounding_box.set_x(self.bounding_box.x() + x);
     }
 
     fn right(&self) -> i16 {
-        self.bounding_boxes()
-            .last()
-            .unwrap_or(&Rect::default())
-            .right()
+        self.bounding_box.right()
+    }
+
+    fn update(&mut self, boy: &mut RedHatBoy) {
+        self.frame = if self.frame < COLLECTIBLE_FRAMES {
+            self.frame + 1
+        } else {
+            0
+        };
+        if !self.collected && boy.bounding_box().intersects(&self.bounding_box) {
+            self.collected = true;
+            boy.collect(self.kind.points());
+            if let Err(err) = self.assets.audio.play_sound(&self.assets.sound) {
+                log!("Error playing pickup sound {:#?}", err);
+            }
+        }
+    }
+}
+
+// Something hanging on ropes from above the screen. It reaches all the way
+// up, so the only way past is to slide underneath.
+pub struct Ceiling {
+    sheet: Rc<SpriteSheet>,
+    sprite: Option<Cell>,
+    bounding_box: Rect,
+}
+
+impl Ceiling {
+    pub fn new(sheet: Rc<SpriteSheet>, kind: CeilingKind, x: i16, bottom: i16) -> Self {
+        let sprite = sheet.cell(kind.frame_name()).cloned();
+        let width = sprite.as_ref().map_or(0, |sprite| sprite.frame.w);
+        Ceiling {
+            sheet,
+            sprite,
+            bounding_box: Rect::new_from_x_y(x, 0, width, bottom),
+        }
+    }
+}
+
+impl Obstacle for Ceiling {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Ceiling
+    }
+
+    fn check_intersection(&self, boy: &mut RedHatBoy) {
+        if boy.bounding_box().intersects(&self.bounding_box) {
+            boy.hit();
+        }
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        if let Some(sprite) = &self.sprite {
+            let top = self.bounding_box.bottom() - sprite.frame.h;
+            [1, 3].iter().for_each(|quarter| {
+                let x = self.bounding_box.x() + self.bounding_box.width * quarter / 4;
+                renderer.draw_line(&Point { x, y: 0 }, &Point { x, y: top }, ROPE_COLOR, 3.0);
+            });
+            self.sheet.draw(
+                renderer,
+                &Rect::new_from_x_y(
+                    sprite.frame.x,
+                    sprite.frame.y,
+                    sprite.frame.w,
+                    sprite.frame.h,
+                ),
+                &Rect::new_from_x_y(self.bounding_box.x(), top, sprite.frame.w, sprite.frame.h),
+            );
+        }
+    }
+
+    fn move_horizontally(&mut self, x: i16) {
+        self.bounding_box.set_x(self.bounding_box.x() + x);
+    }
+
+    fn right(&self) -> i16 {
+        self.bounding_box.right()
     }
 }
 
@@ -485,9 +1454,19 @@ impl Barrier {
 }
 
 impl Obstacle for Barrier {
+    fn kind(&self) -> ObstacleKind {
+        ObstacleKind::Stone
+    }
+
     fn check_intersection(&self, boy: &mut RedHatBoy) {
         if boy.bounding_box().intersects(self.image.bounding_box()) {
-            boy.knock_out();
+            boy.hit();
+        }
+    }
+
+    fn check_dog_intersection(&self, dog: &mut Dog) {
+        if dog.bounding_box().intersects(self.image.bounding_box()) {
+            dog.stumble();
         }
     }
 
@@ -504,6 +1483,64 @@ impl Obstacle for Barrier {
     }
 }
 
+// The end of a level. It is drawn from its tile when it has one and as an
+// outline otherwise, and is reached once the boy runs past its left edge.
+pub struct Goal {
+    sheet: Rc<SpriteSheet>,
+    sprite: Option<Cell>,
+    bounds: Rect,
+}
+
+impl Goal {
+    fn new(sheet: Rc<SpriteSheet>, flag: &GoalFlag) -> Self {
+        let sprite = flag
+            .sprite
+            .as_ref()
+            .and_then(|sprite_name| sheet.cell(sprite_name).cloned());
+        Goal {
+            sheet,
+            sprite,
+            bounds: Rect::new_from_x_y(flag.bounds.x, flag.bounds.y, flag.bounds.w, flag.bounds.h),
+        }
+    }
+
+    fn reached_by(&self, boy: &RedHatBoy) -> bool {
+        boy.bounding_box().right() > self.bounds.x()
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        match &self.sprite {
+            Some(sprite) => self.sheet.draw(
+                renderer,
+                &Rect::new_from_x_y(
+                    sprite.frame.x,
+                    sprite.frame.y,
+                    sprite.frame.w,
+                    sprite.frame.h,
+                ),
+                &self.bounds,
+            ),
+            None => renderer.draw_rect(&self.bounds),
+        }
+    }
+
+    fn move_horizontally(&mut self, x: i16) {
+        self.bounds.set_x(self.bounds.x() + x);
+    }
+}
+
+// Digs each pit out of the ground and returns where the last one ends.
+fn dig_pits(ground: &mut Ground, pits: &[Pit], offset_x: i16) -> i16 {
+    pits.iter()
+        .map(|pit| {
+            let left = offset_x + pit.x;
+            ground.dig(left, left + pit.width);
+            left + pit.width
+        })
+        .max()
+        .unwrap_or(0)
+}
+
 fn rightmost(obstacle_list: &[Box<dyn Obstacle>]) -> i16 {
     obstacle_list
         .iter()
@@ -515,18 +1552,31 @@ fn rightmost(obstacle_list: &[Box<dyn Obstacle>]) -> i16 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use engine::{Sheet, Sound};
-    use futures::channel::mpsc::unbounded;
+    use crate::difficulty::{DifficultyCurve, Stage};
+    use engine::Sheet;
     use std::collections::HashMap;
     use wasm_bindgen_test::wasm_bindgen_test;
     use web_sys::{AudioBuffer, AudioBufferOptions};
 
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
-    #[wasm_bindgen_test]
-    #[allow(dead_code)]
-    fn test_transition_from_game_over_to_new_game() {
-        let (_, receiver) = unbounded();
+    const SEGMENTS: &str = r#"{
+        "start": "empty",
+        "platforms": {},
+        "segments": [
+            { "name": "empty", "difficulty": 1 },
+            { "name": "near_stone", "difficulty": 1, "barriers": [{ "x": 100, "y": 496 }] },
+            { "name": "far_stone", "difficulty": 1, "barriers": [{ "x": 300, "y": 496 }] },
+            {
+                "name": "wall",
+                "difficulty": 1,
+                "requires": "DoubleJump",
+                "barriers": [{ "x": 150, "y": 496 }, { "x": 240, "y": 496 }]
+            }
+        ]
+    }"#;
+
+    fn test_walk() -> Walk {
         let image = HtmlImageElement::new().unwrap();
         let audio = Audio::new().unwrap();
         let options = AudioBufferOptions::new(1, 3000.0);
@@ -538,8 +1588,10 @@ mod tests {
                 frames: HashMap::new(),
             },
             image.clone(),
-            audio,
-            sound,
+            audio.clone(),
+            sound.clone(),
+            sound.clone(),
+            Abilities::default(),
         );
         let sprite_sheet = SpriteSheet::new(
             Sheet {
@@ -547,32 +1599,170 @@ mod tests {
             },
             image.clone(),
         );
-        let walk = Walk {
+        Walk {
             boy: rhb,
+            dog: Dog::new(
+                Sheet {
+                    frames: HashMap::new(),
+                },
+                image.clone(),
+            ),
             backgrounds: [
                 Image::new(image.clone(), Point { x: 0, y: 0 }),
                 Image::new(image.clone(), Point { x: 0, y: 0 }),
             ],
+            ground: Ground::default(),
             obstacles: vec![],
             obstacle_sheet: Rc::new(sprite_sheet),
+            collectible_assets: Rc::new(CollectibleAssets {
+                sheet: SpriteSheet::new(
+                    Sheet {
+                        frames: HashMap::new(),
+                    },
+                    image.clone(),
+                ),
+                audio: audio.clone(),
+                sound: sound.clone(),
+            }),
+            ceiling_sheet: Rc::new(SpriteSheet::new(
+                Sheet {
+                    frames: HashMap::new(),
+                },
+                image.clone(),
+            )),
             stone: image.clone(),
+            selector: SegmentSelector::default(),
+            level: None,
+            course: Course::Random,
+            tutorial: Rc::new(TutorialScript::default()),
+            goal: None,
+            segments: Rc::new(js_sys::JSON::parse(SEGMENTS).unwrap().try_into().unwrap()),
             timeline: 0,
-        };
+            distance: 0,
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+            daily: None,
+            run: RunState::default(),
+            ghosts: Ghosts::default(),
+            difficulty: DifficultyDirector::new(
+                DifficultyCurve::new(vec![Stage {
+                    distance: 0,
+                    running_speed: 4,
+                    obstacle_buffer: 20,
+                    max_segment_difficulty: 1,
+                    unlocks: vec![],
+                }])
+                .unwrap(),
+            ),
+            draft: SegmentDraft::default(),
+            high_scores: HighScores::default(),
+            daily_scores: DailyScores::default(),
+            achievements: Achievements::default(),
+            settings: Settings::default(),
+            audio: audio.clone(),
+            background_music: sound.clone(),
+            music_started: false,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    #[allow(dead_code)]
+    fn test_transition_from_game_over_to_new_game() {
+        let walk = test_walk();
         let document = browser::document().unwrap();
         document
             .body()
             .unwrap()
             .insert_adjacent_html("afterbegin", "<div id='ui'></div>")
             .unwrap();
-        browser::draw_ui("<p>This is the UI</p>").unwrap();
+        let mut panel = Panel::new().unwrap();
+        panel.label("This is the UI").unwrap();
         let state = WalkTheDogState {
-            _state: GameOver {
-                new_game_event: receiver,
-            },
-            walk,
+            _state: GameOver { panel },
+            walk: Box::new(walk),
         };
         state.new_game();
-        let ui = browser::find_html_element_by_id("ui").unwrap();
+        let ui = document.get_element_by_id("ui").unwrap();
         assert_eq!(ui.child_element_count(), 0);
     }
+
+    #[wasm_bindgen_test]
+    #[allow(dead_code)]
+    fn platform_side_hits_while_invulnerable_do_not_knock_out() {
+        let image = HtmlImageElement::new().unwrap();
+        let options = AudioBufferOptions::new(1, 3000.0);
+        let sound = Sound {
+            buffer: AudioBuffer::new(&options).unwrap(),
+        };
+        let mut boy = RedHatBoy::new(
+            js_sys::JSON::parse(include_str!("../static/rhb.json"))
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            image.clone(),
+            Audio::new().unwrap(),
+            sound.clone(),
+            sound,
+            Abilities::default(),
+        );
+        boy.run_right();
+        boy.update();
+        let tiles = SpriteSheet::new(
+            js_sys::JSON::parse(include_str!("../static/tiles.json"))
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            image,
+        );
+        let in_front = boy.bounding_box();
+        let platform = Platform::new(
+            Rc::new(tiles),
+            Point {
+                x: in_front.x(),
+                y: in_front.y(),
+            },
+            &[vec!["1.png", "2.png", "7.png"]],
+        );
+
+        platform.check_intersection(&mut boy);
+        platform.check_intersection(&mut boy);
+
+        assert_eq!(boy.lives(), 2);
+    }
+
+    // Lays out the first few segments of the daily course, returning where
+    // every obstacle on it ends.
+    fn daily_course(walk: Walk) -> Vec<i16> {
+        let mut walk = Walk::daily(walk, "2026-10-18".to_string());
+        (0..8).for_each(|_| walk.generate_next_segment());
+        walk.obstacles
+            .iter()
+            .map(|obstacle| obstacle.right())
+            .collect()
+    }
+
+    #[wasm_bindgen_test]
+    #[allow(dead_code)]
+    fn daily_course_is_the_same_whatever_was_played_before() {
+        let fresh = test_walk();
+
+        let mut played = Walk::reset(test_walk());
+        (0..5).for_each(|_| played.generate_next_segment());
+        let segments = Rc::clone(&played.segments);
+        let practised = Walk::practice(played, segments.segment("wall").unwrap());
+
+        assert_eq!(daily_course(fresh), daily_course(practised));
+    }
+
+    #[wasm_bindgen_test]
+    #[allow(dead_code)]
+    fn practice_abilities_only_last_for_the_practice_run() {
+        let walk = test_walk();
+        let segments = Rc::clone(&walk.segments);
+        let walk = Walk::practice(walk, segments.segment("wall").unwrap());
+        assert!(walk.boy.abilities().has(Ability::DoubleJump));
+
+        let walk = Walk::reset(walk);
+        assert!(!walk.boy.abilities().has(Ability::DoubleJump));
+    }
 }
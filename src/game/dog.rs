@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+
+use dog_states::*;
+use web_sys::HtmlImageElement;
+
+use crate::engine::{Cell, Point, Rect, Renderer, Sheet};
+
+use super::HEIGHT;
+
+mod dog_states;
+
+const FOLLOW_DISTANCE: i16 = 70;
+const LEASH_SLACK: f64 = 40.0;
+const LEASH_SNAP_LENGTH: f64 = 360.0;
+const CATCH_UP_SPEED: i16 = 2;
+const COLLAR_OFFSET: Point = Point { x: 56, y: 16 };
+const LEASH_COLOR: (f64, f64, f64) = (110.0, 60.0, 20.0);
+const TAUT_COLOR: (f64, f64, f64) = (220.0, 30.0, 30.0);
+
+// The boy's jumps, kept where he left the ground so the dog takes off from
+// the same spot however far behind it is.
+#[derive(Clone)]
+struct Takeoff {
+    x: i16,
+    event: DogEvent,
+}
+
+// The dog trots along behind the boy on a leash, repeating his jumps when
+// it reaches the place he jumped from. If it falls too far behind, or into
+// a pit, the leash snaps and it is left behind.
+#[derive(Clone)]
+pub struct Dog {
+    state_machine: DogStateMachine,
+    sprite_sheet: Sheet,
+    image: HtmlImageElement,
+    takeoffs: VecDeque<Takeoff>,
+    leash_snapped: bool,
+}
+
+impl Dog {
+    pub fn new(sprite_sheet: Sheet, image: HtmlImageElement) -> Self {
+        Dog {
+            state_machine: DogStateMachine::new(),
+            sprite_sheet,
+            image,
+            takeoffs: VecDeque::new(),
+            leash_snapped: false,
+        }
+    }
+
+    pub fn reset(dog: Self) -> Self {
+        Dog::new(dog.sprite_sheet, dog.image)
+    }
+
+    pub fn run_right(&mut self) {
+        self.transition(DogEvent::Run);
+    }
+
+    pub fn follow_jump(&mut self, x: i16, double_jump: bool) {
+        if self.leash_snapped {
+            return;
+        }
+        let event = if double_jump {
+            DogEvent::DoubleJump
+        } else {
+            DogEvent::Jump
+        };
+        self.takeoffs.push_back(Takeoff { x, event });
+    }
+
+    pub fn stumble(&mut self) {
+        self.transition(DogEvent::Stumble);
+    }
+
+    pub fn land_on(&mut self, position: i16) {
+        self.transition(DogEvent::Land(position));
+    }
+
+    pub fn set_over_ground(&mut self, over_ground: bool) {
+        self.state_machine.context_mut().over_ground = over_ground;
+    }
+
+    pub fn pos_y(&self) -> i16 {
+        self.state_machine.context().position.y
+    }
+
+    pub fn velocity_y(&self) -> i16 {
+        self.state_machine.context().velocity.y
+    }
+
+    pub fn bounding_box(&self) -> Rect {
+        self.state_machine.bounding_box(&self.sprite_sheet)
+    }
+
+    // Moves with the scrolling world, then closes in on its place behind the
+    // boy unless it is idle, stumbling or has been let go.
+    pub fn update(&mut self, scroll: i16, boy_x: i16) {
+        if self.left_behind() {
+            return;
+        }
+        self.takeoffs
+            .iter_mut()
+            .for_each(|takeoff| takeoff.x += scroll);
+        let x = self.bounding_box().x();
+        while self
+            .takeoffs
+            .front()
+            .map_or(false, |takeoff| takeoff.x <= x)
+        {
+            if let Some(takeoff) = self.takeoffs.pop_front() {
+                self.transition(takeoff.event);
+            }
+        }
+
+        self.state_machine = self.state_machine.clone().update();
+        let held_back = self.leash_snapped || !self.state_machine.following();
+        let context = self.state_machine.context_mut();
+        if held_back {
+            context.position.x += scroll;
+        } else {
+            let target = boy_x - FOLLOW_DISTANCE;
+            context.position.x +=
+                (target - context.position.x).clamp(-CATCH_UP_SPEED, CATCH_UP_SPEED);
+        }
+    }
+
+    // Snaps the leash once it is stretched past breaking or the dog has
+    // dropped out of sight.
+    pub fn pull(&mut self, hand: Point) {
+        if self.leash_length(hand) > LEASH_SNAP_LENGTH || self.pos_y() > HEIGHT {
+            self.leash_snapped = true;
+            self.takeoffs.clear();
+        }
+    }
+
+    // Once let go and out of sight there is nothing more to update.
+    fn left_behind(&self) -> bool {
+        self.leash_snapped && (self.bounding_box().right() < 0 || self.pos_y() > HEIGHT)
+    }
+
+    fn collar(&self) -> Point {
+        let position = self.state_machine.context().position;
+        Point {
+            x: position.x + COLLAR_OFFSET.x,
+            y: position.y + COLLAR_OFFSET.y,
+        }
+    }
+
+    fn leash_length(&self, hand: Point) -> f64 {
+        let collar = self.collar();
+        f64::from(hand.x - collar.x).hypot(f64::from(hand.y - collar.y))
+    }
+
+    pub fn draw(&self, renderer: &Renderer) {
+        let sprite = self
+            .state_machine
+            .current_sprite(&self.sprite_sheet)
+            .expect("Cell not found");
+
+        renderer.draw_image(
+            &self.image,
+            &Rect::new_from_x_y(
+                sprite.frame.x,
+                sprite.frame.y,
+                sprite.frame.w,
+                sprite.frame.h,
+            ),
+            &self.state_machine.destination_box(&self.sprite_sheet),
+        );
+    }
+
+    // The leash reddens and thins as it stretches towards snapping.
+    pub fn draw_leash(&self, renderer: &Renderer, hand: Point) {
+        if self.leash_snapped {
+            return;
+        }
+        let tension = ((self.leash_length(hand) - LEASH_SLACK) / (LEASH_SNAP_LENGTH - LEASH_SLACK))
+            .clamp(0.0, 1.0);
+        let mix = |relaxed: f64, taut: f64| relaxed + (taut - relaxed) * tension;
+        let color = format!(
+            "rgb({}, {}, {})",
+            mix(LEASH_COLOR.0, TAUT_COLOR.0),
+            mix(LEASH_COLOR.1, TAUT_COLOR.1),
+            mix(LEASH_COLOR.2, TAUT_COLOR.2)
+        );
+        renderer.draw_line(&hand, &self.collar(), &color, mix(3.0, 1.0));
+    }
+
+    fn transition(&mut self, event: DogEvent) {
+        self.state_machine = self.state_machine.clone().transition(event);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DogEvent {
+    Run,
+    Jump,
+    DoubleJump,
+    Stumble,
+    Land(i16),
+    Update,
+}
+
+#[derive(Clone)]
+pub(crate) enum DogStateMachine {
+    Idle(DogState<Idle>),
+    Running(DogState<Running>),
+    Jumping(DogState<Jumping>),
+    Stumbling(DogState<Stumbling>),
+}
+
+impl DogStateMachine {
+    pub(crate) fn new() -> Self {
+        DogStateMachine::Idle(DogState::new())
+    }
+
+    fn following(&self) -> bool {
+        matches!(
+            self,
+            DogStateMachine::Running(_) | DogStateMachine::Jumping(_)
+        )
+    }
+
+    pub(crate) fn transition(self, event: DogEvent) -> Self {
+        match (self.clone(), event) {
+            (DogStateMachine::Idle(state), DogEvent::Run) => state.run().into(),
+            (DogStateMachine::Idle(state), DogEvent::Update) => state.update().into(),
+
+            (DogStateMachine::Running(state), DogEvent::Jump) => state.jump().into(),
+            (DogStateMachine::Running(state), DogEvent::Stumble) => state.stumble().into(),
+            (DogStateMachine::Running(state), DogEvent::Land(position)) => {
+                state.land_on(position).into()
+            }
+            (DogStateMachine::Running(state), DogEvent::Update) => state.update().into(),
+
+            (DogStateMachine::Jumping(state), DogEvent::DoubleJump) => state.double_jump().into(),
+            (DogStateMachine::Jumping(state), DogEvent::Stumble) => state.stumble().into(),
+            (DogStateMachine::Jumping(state), DogEvent::Land(position)) => {
+                state.land_on(position).into()
+            }
+            (DogStateMachine::Jumping(state), DogEvent::Update) => state.update().into(),
+
+            (DogStateMachine::Stumbling(state), DogEvent::Land(position)) => {
+                state.land_on(position).into()
+            }
+            (DogStateMachine::Stumbling(state), DogEvent::Update) => state.update().into(),
+            _ => self,
+        }
+    }
+
+    fn frame_name(&self) -> &str {
+        match self {
+            DogStateMachine::Idle(state) => state.frame_name(),
+            DogStateMachine::Running(state) => state.frame_name(),
+            DogStateMachine::Jumping(state) => state.frame_name(),
+            DogStateMachine::Stumbling(state) => state.frame_name(),
+        }
+    }
+
+    pub(crate) fn context(&self) -> &DogContext {
+        match self {
+            DogStateMachine::Idle(state) => state.context(),
+            DogStateMachine::Running(state) => state.context(),
+            DogStateMachine::Jumping(state) => state.context(),
+            DogStateMachine::Stumbling(state) => state.context(),
+        }
+    }
+
+    pub(crate) fn context_mut(&mut self) -> &mut DogContext {
+        match self {
+            DogStateMachine::Idle(state) => state.context_mut(),
+            DogStateMachine::Running(state) => state.context_mut(),
+            DogStateMachine::Jumping(state) => state.context_mut(),
+            DogStateMachine::Stumbling(state) => state.context_mut(),
+        }
+    }
+
+    pub(crate) fn update(self) -> Self {
+        self.transition(DogEvent::Update)
+    }
+
+    fn sprite_name(&self) -> String {
+        format!(
+            "{} ({}).png",
+            self.frame_name(),
+            (self.context().frame / 3) + 1
+        )
+    }
+
+    fn current_sprite<'a>(&self, sprite_sheet: &'a Sheet) -> Option<&'a Cell> {
+        sprite_sheet.cell(&self.sprite_name())
+    }
+
+    pub(crate) fn bounding_box(&self, sprite_sheet: &Sheet) -> Rect {
+        const X_OFFSET: i16 = 10;
+        const Y_OFFSET: i16 = 12;
+        const WIDTH_OFFSET: i16 = 16;
+        let mut bounding_box = self.destination_box(sprite_sheet);
+        bounding_box.set_x(bounding_box.x() + X_OFFSET);
+        bounding_box.width -= WIDTH_OFFSET;
+        bounding_box.set_y(bounding_box.y() + Y_OFFSET);
+        bounding_box.height -= Y_OFFSET;
+
+        bounding_box
+    }
+
+    fn destination_box(&self, sprite_sheet: &Sheet) -> Rect {
+        let sprite = self.current_sprite(sprite_sheet).expect("Cell not found");
+        Rect::new_from_x_y(
+            self.context().position.x + sprite.sprite_source_size.x,
+            self.context().position.y + sprite.sprite_source_size.y,
+            sprite.frame.w,
+            sprite.frame.h,
+        )
+    }
+}
+
+impl From<DogState<Idle>> for DogStateMachine {
+    fn from(state: DogState<Idle>) -> Self {
+        DogStateMachine::Idle(state)
+    }
+}
+
+impl From<DogState<Running>> for DogStateMachine {
+    fn from(state: DogState<Running>) -> Self {
+        DogStateMachine::Running(state)
+    }
+}
+
+impl From<DogState<Jumping>> for DogStateMachine {
+    fn from(state: DogState<Jumping>) -> Self {
+        DogStateMachine::Jumping(state)
+    }
+}
+
+impl From<DogState<Stumbling>> for DogStateMachine {
+    fn from(state: DogState<Stumbling>) -> Self {
+        DogStateMachine::Stumbling(state)
+    }
+}
+
+impl From<DogRunningEndState> for DogStateMachine {
+    fn from(end_state: DogRunningEndState) -> Self {
+        match end_state {
+            DogRunningEndState::Running(running_state) => running_state.into(),
+            DogRunningEndState::Jumping(jumping_state) => jumping_state.into(),
+        }
+    }
+}
+
+impl From<DogStumblingEndState> for DogStateMachine {
+    fn from(end_state: DogStumblingEndState) -> Self {
+        match end_state {
+            DogStumblingEndState::Complete(running_state) => running_state.into(),
+            DogStumblingEndState::Stumbling(stumbling_state) => stumbling_state.into(),
+        }
+    }
+}
+
+impl<S> From<DogLandingEndState<S>> for DogStateMachine
+where
+    DogState<S>: Into<DogStateMachine>,
+{
+    fn from(end_state: DogLandingEndState<S>) -> Self {
+        match end_state {
+            DogLandingEndState::Complete(running_state) => running_state.into(),
+            DogLandingEndState::InAir(in_air_state) => in_air_state.into(),
+        }
+    }
+}
+
+impl<S> From<DogStumbleEndState<S>> for DogStateMachine
+where
+    DogState<S>: Into<DogStateMachine>,
+{
+    fn from(end_state: DogStumbleEndState<S>) -> Self {
+        match end_state {
+            DogStumbleEndState::Unharmed(unharmed_state) => unharmed_state.into(),
+            DogStumbleEndState::Stumbling(stumbling_state) => stumbling_state.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jumps_land_and_stumbles_wear_off() {
+        let json = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/static/dog.json"))
+            .unwrap();
+        let sheet: Sheet = serde_json::from_str(&json).unwrap();
+        let floor = DogStateMachine::new().context().position.y;
+
+        let mut dog = DogStateMachine::new()
+            .transition(DogEvent::Run)
+            .transition(DogEvent::Jump)
+            .update()
+            .transition(DogEvent::DoubleJump);
+        assert!(dog.context().position.y < floor);
+        while matches!(dog, DogStateMachine::Jumping(_)) {
+            dog = dog.update();
+            assert!(dog.current_sprite(&sheet).is_some());
+        }
+        assert!(matches!(dog, DogStateMachine::Running(_)));
+        assert_eq!(dog.context().position.y, floor);
+
+        let mut dog = dog.transition(DogEvent::Stumble);
+        assert!(matches!(dog, DogStateMachine::Stumbling(_)));
+        while matches!(dog, DogStateMachine::Stumbling(_)) {
+            dog = dog.update();
+        }
+        assert!(matches!(
+            dog.clone().transition(DogEvent::Stumble),
+            DogStateMachine::Running(_)
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{engine::Point, game::GROUND};
+
+const DOG_HEIGHT: i16 = 56;
+const FLOOR: i16 = GROUND - DOG_HEIGHT;
+const STARTING_POINT: i16 = -40;
+const JUMP_SPEED: i16 = -25;
+const DOUBLE_JUMP_SPEED: i16 = -20;
+const STUMBLE_HOP: i16 = -6;
+const STUMBLE_RECOVERY_FRAMES: u8 = 60;
+const GRAVITY: i16 = 1;
+const TERMINAL_VELOCITY: i16 = 20;
+
+const IDLE_FRAMES: u8 = 11;
+const RUNNING_FRAMES: u8 = 23;
+const JUMPING_FRAMES: u8 = 11;
+const STUMBLING_FRAMES: u8 = 11;
+const IDLE_FRAME_NAME: &str = "Idle";
+const RUN_FRAME_NAME: &str = "Run";
+const JUMPING_FRAME_NAME: &str = "Jump";
+const STUMBLING_FRAME_NAME: &str = "Stumble";
+
+#[derive(Clone)]
+pub struct DogState<S> {
+    context: DogContext,
+    _state: S,
+}
+
+impl<S> DogState<S> {
+    pub fn context(&self) -> &DogContext {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut DogContext {
+        &mut self.context
+    }
+
+    fn update_context(&mut self, frames: u8) {
+        self.context = self.context.clone().update(frames);
+    }
+
+    fn update_in_air(mut self, frames: u8) -> DogLandingEndState<S> {
+        self.update_context(frames);
+
+        if self.context.on_floor() {
+            DogLandingEndState::Complete(self.land(GROUND))
+        } else {
+            DogLandingEndState::InAir(self)
+        }
+    }
+
+    fn land(self, position: i16) -> DogState<Running> {
+        DogState {
+            context: self.context.reset_frame().set_on(position),
+            _state: Running,
+        }
+    }
+
+    fn unsupported(&self) -> bool {
+        self.context.velocity.y > GRAVITY && self.context.position.y != FLOOR
+    }
+
+    fn take_stumble(self) -> DogStumbleEndState<S> {
+        if self.context.recovering_frames > 0 {
+            DogStumbleEndState::Unharmed(self)
+        } else {
+            DogStumbleEndState::Stumbling(DogState {
+                context: self.context.reset_frame().stumble(),
+                _state: Stumbling,
+            })
+        }
+    }
+}
+
+pub enum DogLandingEndState<S> {
+    Complete(DogState<Running>),
+    InAir(DogState<S>),
+}
+
+pub enum DogStumbleEndState<S> {
+    Unharmed(DogState<S>),
+    Stumbling(DogState<Stumbling>),
+}
+
+#[derive(Clone)]
+pub struct DogContext {
+    pub frame: u8,
+    pub position: Point,
+    pub velocity: Point,
+    pub recovering_frames: u8,
+    pub over_ground: bool,
+}
+
+impl DogContext {
+    fn update(mut self, frame_count: u8) -> Self {
+        if self.velocity.y < TERMINAL_VELOCITY {
+            self.velocity.y += GRAVITY;
+        }
+
+        if self.frame < frame_count {
+            self.frame += 1;
+        } else {
+            self.frame = 0;
+        }
+
+        self.recovering_frames = self.recovering_frames.saturating_sub(1);
+
+        // Like the boy, the dog only stops on the floor when coming down
+        // onto it.
+        let above_floor = self.position.y <= FLOOR;
+        self.position.y += self.velocity.y;
+
+        if self.over_ground && above_floor && self.position.y > FLOOR {
+            self.position.y = FLOOR;
+            self.velocity.y = 0;
+        }
+
+        self
+    }
+
+    fn on_floor(&self) -> bool {
+        self.position.y == FLOOR && self.velocity.y == 0
+    }
+
+    fn reset_frame(mut self) -> Self {
+        self.frame = 0;
+        self
+    }
+
+    fn set_vertical_velocity(mut self, y: i16) -> Self {
+        self.velocity.y = y;
+        self
+    }
+
+    fn stumble(mut self) -> Self {
+        self.velocity.y = STUMBLE_HOP;
+        self.recovering_frames = STUMBLE_RECOVERY_FRAMES;
+        self
+    }
+
+    fn set_on(mut self, position: i16) -> Self {
+        self.position.y = position - DOG_HEIGHT;
+        self.velocity.y = 0;
+        self
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Idle;
+
+impl DogState<Idle> {
+    pub fn new() -> Self {
+        DogState {
+            context: DogContext {
+                frame: 0,
+                position: Point {
+                    x: STARTING_POINT,
+                    y: FLOOR,
+                },
+                velocity: Point { x: 0, y: 0 },
+                recovering_frames: 0,
+                over_ground: true,
+            },
+            _state: Idle,
+        }
+    }
+
+    pub fn frame_name(&self) -> &str {
+        IDLE_FRAME_NAME
+    }
+
+    pub fn update(mut self) -> Self {
+        self.update_context(IDLE_FRAMES);
+        self
+    }
+
+    pub fn run(self) -> DogState<Running> {
+        DogState {
+            context: self.context.reset_frame(),
+            _state: Running,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Running;
+
+pub enum DogRunningEndState {
+    Running(DogState<Running>),
+    Jumping(DogState<Jumping>),
+}
+
+impl DogState<Running> {
+    pub fn frame_name(&self) -> &str {
+        RUN_FRAME_NAME
+    }
+
+    pub fn update(mut self) -> DogRunningEndState {
+        self.update_context(RUNNING_FRAMES);
+
+        if self.unsupported() {
+            DogRunningEndState::Jumping(DogState {
+                context: self.context,
+                _state: Jumping,
+            })
+        } else {
+            DogRunningEndState::Running(self)
+        }
+    }
+
+    pub fn jump(self) -> DogState<Jumping> {
+        DogState {
+            context: self.context.reset_frame().set_vertical_velocity(JUMP_SPEED),
+            _state: Jumping,
+        }
+    }
+
+    pub fn stumble(self) -> DogStumbleEndState<Running> {
+        self.take_stumble()
+    }
+
+    pub fn land_on(self, position: i16) -> DogState<Running> {
+        DogState {
+            context: self.context.set_on(position),
+            _state: Running,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Jumping;
+
+impl DogState<Jumping> {
+    pub fn frame_name(&self) -> &str {
+        JUMPING_FRAME_NAME
+    }
+
+    pub fn update(self) -> DogLandingEndState<Jumping> {
+        self.update_in_air(JUMPING_FRAMES)
+    }
+
+    pub fn double_jump(self) -> Self {
+        DogState {
+            context: self.context.set_vertical_velocity(DOUBLE_JUMP_SPEED),
+            _state: Jumping,
+        }
+    }
+
+    pub fn stumble(self) -> DogStumbleEndState<Jumping> {
+        self.take_stumble()
+    }
+
+    pub fn land_on(self, position: i16) -> DogState<Running> {
+        self.land(position)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Stumbling;
+
+pub enum DogStumblingEndState {
+    Complete(DogState<Running>),
+    Stumbling(DogState<Stumbling>),
+}
+
+impl DogState<Stumbling> {
+    pub fn frame_name(&self) -> &str {
+        STUMBLING_FRAME_NAME
+    }
+
+    pub fn update(mut self) -> DogStumblingEndState {
+        self.update_context(STUMBLING_FRAMES);
+
+        if self.context.frame >= STUMBLING_FRAMES {
+            DogStumblingEndState::Complete(DogState {
+                context: self.context.reset_frame(),
+                _state: Running,
+            })
+        } else {
+            DogStumblingEndState::Stumbling(self)
+        }
+    }
+
+    pub fn land_on(self, position: i16) -> DogState<Stumbling> {
+        DogState {
+            context: self.context.set_on(position),
+            _state: Stumbling,
+        }
+    }
+}
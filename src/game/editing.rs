@@ -0,0 +1,346 @@
+use super::{
+    lay_out_tiles, Obstacle, Ready, Walk, WalkTheDogState, WalkTheDogStateMachine, Walking, GROUND,
+};
+use crate::{
+    browser::{
+        self,
+        ui::{Panel, UiEvent},
+    },
+    editor::{snap, DraftItem},
+    engine::{self, KeyState, MouseDownListener, Point, Rect, Renderer},
+    segments::PlatformPlacement,
+};
+
+const EDITOR_SEGMENT_NAME: &str = "custom";
+const SCROLL_SPEED: i16 = 8;
+const PALETTE_SLOT: i16 = 100;
+const PALETTE_HEIGHT: i16 = 80;
+const PALETTE_MARGIN: i16 = 10;
+const HINTS_POSITION: Point = Point { x: 20, y: 120 };
+const HINT_LINE_HEIGHT: i16 = 26;
+const HINTS: [&str; 4] = [
+    "1-9 or click: choose a piece",
+    "Click: place or remove",
+    "Arrows: scroll  Z/Y: undo/redo",
+    "P: play  X: export  Esc: exit",
+];
+
+pub struct Editing {
+    clicks: MouseDownListener,
+    tool: usize,
+    scroll: i16,
+    // The draft laid out where it is drawn, only built again when the
+    // draft changes.
+    pieces: Vec<Box<dyn Obstacle>>,
+    // The export form, on the page until the editor is left.
+    export: Option<Panel<ExportField>>,
+    export_name: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ExportField {
+    Name,
+    Export,
+}
+
+enum Tool {
+    Stone,
+    Platform(String),
+}
+
+pub enum EditingEndState {
+    Continue(WalkTheDogState<Editing>),
+    Play(WalkTheDogState<Walking>),
+    Exit(WalkTheDogState<Ready>),
+}
+
+impl From<EditingEndState> for WalkTheDogStateMachine {
+    fn from(state: EditingEndState) -> Self {
+        match state {
+            EditingEndState::Continue(editing) => editing.into(),
+            EditingEndState::Play(walking) => walking.into(),
+            EditingEndState::Exit(ready) => ready.into(),
+        }
+    }
+}
+
+impl WalkTheDogState<Ready> {
+    pub(super) fn edit(self) -> WalkTheDogState<Editing> {
+        let clicks = browser::canvas()
+            .map(|canvas| engine::add_mouse_down_handler(canvas.into()))
+            .unwrap();
+        let mut editing = WalkTheDogState {
+            _state: Editing {
+                clicks,
+                tool: 0,
+                scroll: 0,
+                pieces: vec![],
+                export: None,
+                export_name: EDITOR_SEGMENT_NAME.to_string(),
+            },
+            walk: self.walk,
+        };
+        editing.lay_out_draft();
+        editing
+    }
+}
+
+impl WalkTheDogState<Editing> {
+    pub(super) fn update(mut self, keystate: &KeyState) -> EditingEndState {
+        if keystate.is_just_pressed("Escape") {
+            return EditingEndState::Exit(self.exit());
+        }
+        if keystate.is_just_pressed("KeyP") {
+            return EditingEndState::Play(self.play());
+        }
+
+        let scroll = self._state.scroll;
+        if keystate.is_pressed("ArrowLeft") {
+            self._state.scroll = (self._state.scroll - SCROLL_SPEED).max(0);
+        }
+        if keystate.is_pressed("ArrowRight") {
+            self._state.scroll += SCROLL_SPEED;
+        }
+        self._state
+            .pieces
+            .iter_mut()
+            .for_each(|piece| piece.move_horizontally(scroll - self._state.scroll));
+
+        let mut edited = false;
+        if keystate.is_just_pressed("KeyZ") {
+            self.walk.draft.undo();
+            edited = true;
+        }
+        if keystate.is_just_pressed("KeyY") {
+            self.walk.draft.redo();
+            edited = true;
+        }
+        if keystate.is_just_pressed("KeyX") {
+            self.show_export(None);
+        }
+        self.update_export();
+        (1..=9)
+            .filter(|digit| keystate.is_just_pressed(&format!("Digit{}", digit)))
+            .for_each(|digit| self.select_tool(digit - 1));
+        while let Some(position) = self._state.clicks.next_click() {
+            self.click(position);
+            edited = true;
+        }
+        if edited {
+            self.lay_out_draft();
+        }
+
+        EditingEndState::Continue(self)
+    }
+
+    pub(super) fn draw_editor(&self, renderer: &Renderer) {
+        self.walk.backgrounds.iter().for_each(|background| {
+            background.draw(renderer);
+        });
+        self._state
+            .pieces
+            .iter()
+            .for_each(|piece| piece.draw(renderer));
+        self.draw_palette(renderer);
+        let pieces = format!("Pieces: {}", self.walk.draft.items().len());
+        std::iter::once(pieces.as_str())
+            .chain(HINTS)
+            .zip(0..)
+            .for_each(|(hint, line)| {
+                let position = Point {
+                    x: HINTS_POSITION.x,
+                    y: HINTS_POSITION.y + line * HINT_LINE_HEIGHT,
+                };
+                if let Err(err) = renderer.draw_text(hint, &position) {
+                    error!("Could not draw editor hint {:#?}", err);
+                }
+            });
+    }
+
+    fn lay_out_draft(&mut self) {
+        self._state.pieces = self.walk.segments.create(
+            &self.walk.draft.to_definition(EDITOR_SEGMENT_NAME),
+            &self.walk.stone,
+            &self.walk.obstacle_sheet,
+            &self.walk.collectible_assets,
+            &self.walk.ceiling_sheet,
+            -self._state.scroll,
+        );
+    }
+
+    fn tools(&self) -> Vec<Tool> {
+        std::iter::once(Tool::Stone)
+            .chain(
+                self.walk
+                    .segments
+                    .platform_names()
+                    .into_iter()
+                    .map(|name| Tool::Platform(name.into())),
+            )
+            .collect()
+    }
+
+    fn select_tool(&mut self, tool: usize) {
+        if tool < self.tools().len() {
+            self._state.tool = tool;
+        }
+    }
+
+    fn stone_size(&self) -> (i16, i16) {
+        (
+            self.walk.stone.width() as i16,
+            self.walk.stone.height() as i16,
+        )
+    }
+
+    fn click(&mut self, position: Point) {
+        if position.y < PALETTE_HEIGHT {
+            self.select_tool((position.x / PALETTE_SLOT) as usize);
+            return;
+        }
+
+        let position = Point {
+            x: position.x + self._state.scroll,
+            y: position.y,
+        };
+        match self.walk.draft.item_at(
+            position,
+            &self.walk.segments,
+            &self.walk.obstacle_sheet,
+            self.stone_size(),
+        ) {
+            Some(index) => self.walk.draft.remove(index),
+            None => self.place(position),
+        }
+    }
+
+    fn place(&mut self, position: Point) {
+        let x = snap(position.x);
+        let y = snap(position.y);
+        let item = match self.tools().swap_remove(self._state.tool) {
+            Tool::Stone => DraftItem::Barrier(Point {
+                x,
+                y: y.min(GROUND - self.stone_size().1),
+            }),
+            Tool::Platform(platform) => DraftItem::Platform(PlatformPlacement {
+                platform,
+                x,
+                y,
+                length: None,
+                path: None,
+            }),
+        };
+        self.walk.draft.place(item);
+    }
+
+    fn draw_palette(&self, renderer: &Renderer) {
+        self.tools().iter().zip(0..).for_each(|(tool, index)| {
+            let slot = Rect::new_from_x_y(
+                index * PALETTE_SLOT + PALETTE_MARGIN,
+                PALETTE_MARGIN,
+                PALETTE_SLOT - PALETTE_MARGIN * 2,
+                PALETTE_HEIGHT - PALETTE_MARGIN * 2,
+            );
+            match tool {
+                Tool::Stone => {
+                    let (width, height) = self.stone_size();
+                    renderer.draw_image(
+                        &self.walk.stone,
+                        &Rect::new_from_x_y(0, 0, width, height),
+                        &Rect::new(slot.position, slot.width, slot.width * height / width),
+                    );
+                }
+                Tool::Platform(name) => self.draw_platform_preview(renderer, name, &slot),
+            }
+            if index as usize == self._state.tool {
+                renderer.draw_rect(&slot);
+            }
+        });
+    }
+
+    fn draw_platform_preview(&self, renderer: &Renderer, name: &str, slot: &Rect) {
+        let sheet = &self.walk.obstacle_sheet;
+        let tiles = self
+            .walk
+            .segments
+            .platform(name)
+            .map(|definition| lay_out_tiles(&definition.rows(None), |sprite| sheet.cell(sprite)))
+            .unwrap_or_default();
+        let total_width = tiles
+            .iter()
+            .map(|(cell, offset)| offset.x + cell.frame.w)
+            .max()
+            .unwrap_or_default();
+        if total_width == 0 {
+            return;
+        }
+        let scale = |value: i16| value * slot.width / total_width;
+        tiles.iter().for_each(|(cell, offset)| {
+            sheet.draw(
+                renderer,
+                &Rect::new_from_x_y(cell.frame.x, cell.frame.y, cell.frame.w, cell.frame.h),
+                &Rect::new_from_x_y(
+                    slot.x() + scale(offset.x),
+                    slot.y() + scale(offset.y),
+                    scale(cell.frame.w),
+                    scale(cell.frame.h),
+                ),
+            );
+        });
+    }
+
+    fn update_export(&mut self) {
+        let mut export = false;
+        while let Some(event) = self._state.export.as_mut().and_then(Panel::next_event) {
+            match event {
+                UiEvent::Changed(ExportField::Name, name) => self._state.export_name = name,
+                UiEvent::Clicked(ExportField::Export) => export = true,
+                _ => {}
+            }
+        }
+        if export {
+            let definition = self.walk.draft.to_definition(&self._state.export_name);
+            match browser::to_json_string(&definition) {
+                Ok(json) => self.show_export(Some(&json)),
+                Err(err) => error!("Could not export segment {:#?}", err),
+            }
+        }
+    }
+
+    // Asks for the segment's name, then shows its JSON to copy into
+    // segments.json under the form.
+    fn show_export(&mut self, json: Option<&str>) {
+        self._state.export = None;
+        let panel = Panel::new().and_then(|mut panel| {
+            panel.label("Segment name")?;
+            panel.input(ExportField::Name, &self._state.export_name)?;
+            panel.button(ExportField::Export, "Export")?;
+            if let Some(json) = json {
+                panel.text_area(json, 20, 40)?;
+            }
+            Ok(panel)
+        });
+        match panel {
+            Ok(panel) => self._state.export = Some(panel),
+            Err(err) => error!("Could not show the export form {:#?}", err),
+        }
+    }
+
+    fn play(self) -> WalkTheDogState<Walking> {
+        let segment = self.walk.draft.to_definition(EDITOR_SEGMENT_NAME);
+        let mut walk = Walk::reset_with_segment(*self.walk, &segment);
+        walk.boy.run_right();
+        walk.dog.run_right();
+        WalkTheDogState {
+            _state: Walking,
+            walk: Box::new(walk),
+        }
+    }
+
+    fn exit(self) -> WalkTheDogState<Ready> {
+        WalkTheDogState {
+            _state: Ready,
+            walk: self.walk,
+        }
+    }
+}
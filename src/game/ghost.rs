@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+
+use super::rhb::RedHatBoy;
+use crate::{
+    browser,
+    engine::{Point, Renderer},
+};
+
+const STORAGE_KEY: &str = "walk_the_dog.ghosts";
+// Ghosts are kept for the most recent daily challenges only, to stay well
+// within what local storage allows.
+const MAX_GHOSTS: usize = 3;
+
+// Where the boy was and how he looked on one update of a run: the distance
+// run so far, the top left of his sprite on screen and which sprite it was,
+// as an index into the ghost's sprite names. Stored as a plain array to keep
+// long runs small.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GhostTick(i32, i16, i16, u16);
+
+// The best run on a course, replayed on later runs of the same seed.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct Ghost {
+    // Seeds use all 64 bits, more than a JavaScript number holds exactly, so
+    // they're stored as text.
+    #[serde(with = "seed_as_text")]
+    seed: u64,
+    score: u32,
+    sprites: Vec<String>,
+    ticks: Vec<GhostTick>,
+}
+
+#[derive(Default)]
+pub struct GhostRecording {
+    sprites: Vec<String>,
+    ticks: Vec<GhostTick>,
+}
+
+impl GhostRecording {
+    pub fn record(&mut self, distance: i32, position: Point, sprite: String) {
+        let index = match self.sprites.iter().position(|name| *name == sprite) {
+            Some(index) => index,
+            None => {
+                self.sprites.push(sprite);
+                self.sprites.len() - 1
+            }
+        };
+        self.ticks
+            .push(GhostTick(distance, position.x, position.y, index as u16));
+    }
+
+    pub fn finish(self, seed: u64, score: u32) -> Ghost {
+        Ghost {
+            seed,
+            score,
+            sprites: self.sprites,
+            ticks: self.ticks,
+        }
+    }
+}
+
+// A ghost being raced, advanced one tick per update.
+pub struct GhostPlayback {
+    ghost: Ghost,
+    tick: usize,
+}
+
+impl GhostPlayback {
+    pub fn new(ghost: Ghost) -> Self {
+        GhostPlayback { ghost, tick: 0 }
+    }
+
+    pub fn update(&mut self) {
+        self.tick += 1;
+    }
+
+    // The ghost's position on screen for a walk that has run `distance`.
+    // Anything it has run further is scrolled ahead of where it was recorded,
+    // and anything less behind. Once its run is over it is gone.
+    fn current(&self, distance: i32) -> Option<(&str, Point)> {
+        let GhostTick(ghost_distance, x, y, sprite) = *self.ghost.ticks.get(self.tick)?;
+        let offset = (ghost_distance - distance).clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+        let name = self.ghost.sprites.get(usize::from(sprite))?;
+        Some((
+            name,
+            Point {
+                x: x.saturating_add(offset),
+                y,
+            },
+        ))
+    }
+
+    pub fn draw(&self, renderer: &Renderer, boy: &RedHatBoy, distance: i32) {
+        if let Some((sprite, position)) = self.current(distance) {
+            boy.draw_ghost(renderer, sprite, position);
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct Ghosts {
+    runs: Vec<Ghost>,
+    // The best run on the endless course being played. Nothing offers its
+    // seed again once it has been left, so it is never saved.
+    #[serde(skip)]
+    endless: Option<Ghost>,
+}
+
+impl Ghosts {
+    pub fn load() -> Self {
+        browser::load(STORAGE_KEY)
+            .unwrap_or_else(|err| {
+                error!("Could not load ghosts {:#?}", err);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = browser::store(STORAGE_KEY, self) {
+            error!("Could not save ghosts {:#?}", err);
+        }
+    }
+
+    pub fn find(&self, seed: u64) -> Option<Ghost> {
+        self.endless
+            .iter()
+            .chain(&self.runs)
+            .find(|ghost| ghost.seed == seed)
+            .cloned()
+    }
+
+    // Keeps the run if it's the best on its course, moving a daily course to
+    // the front. Returns whether anything that is saved changed.
+    pub fn offer(&mut self, ghost: Ghost, daily: bool) -> bool {
+        if !daily {
+            let better = self.endless.as_ref().map_or(true, |best| {
+                best.seed != ghost.seed || best.score < ghost.score
+            });
+            if better {
+                self.endless = Some(ghost);
+            }
+            return false;
+        }
+
+        let previous = self.runs.iter().position(|run| run.seed == ghost.seed);
+        if let Some(index) = previous {
+            if self.runs[index].score >= ghost.score {
+                return false;
+            }
+            self.runs.remove(index);
+        }
+        self.runs.insert(0, ghost);
+        self.runs.truncate(MAX_GHOSTS);
+        true
+    }
+}
+
+mod seed_as_text {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(seed)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ghost(seed: u64, score: u32) -> Ghost {
+        GhostRecording::default().finish(seed, score)
+    }
+
+    #[test]
+    fn recordings_share_sprite_names() {
+        let mut recording = GhostRecording::default();
+        recording.record(0, Point { x: 10, y: 400 }, "Run (1).png".into());
+        recording.record(4, Point { x: 10, y: 400 }, "Run (1).png".into());
+        recording.record(8, Point { x: 10, y: 380 }, "Jump (1).png".into());
+        let ghost = recording.finish(7, 50);
+        assert_eq!(ghost.sprites, ["Run (1).png", "Jump (1).png"]);
+        assert_eq!(ghost.ticks[2], GhostTick(8, 10, 380, 1));
+        assert_eq!(
+            serde_json::to_string(&ghost.ticks[2]).unwrap(),
+            "[8,10,380,1]"
+        );
+    }
+
+    #[test]
+    fn playback_scrolls_by_the_difference_in_distance() {
+        let mut recording = GhostRecording::default();
+        recording.record(0, Point { x: 10, y: 400 }, "Run (1).png".into());
+        recording.record(6, Point { x: 10, y: 400 }, "Run (2).png".into());
+        let mut playback = GhostPlayback::new(recording.finish(7, 50));
+        assert_eq!(
+            playback.current(0),
+            Some(("Run (1).png", Point { x: 10, y: 400 }))
+        );
+
+        playback.update();
+        assert_eq!(
+            playback.current(4),
+            Some(("Run (2).png", Point { x: 12, y: 400 }))
+        );
+        playback.update();
+        assert_eq!(playback.current(8), None);
+    }
+
+    #[test]
+    fn only_the_best_run_on_each_recent_course_is_kept() {
+        let mut ghosts = Ghosts::default();
+        assert!(ghosts.offer(ghost(1, 30), true));
+        assert!(!ghosts.offer(ghost(1, 20), true));
+        assert!(ghosts.offer(ghost(1, 40), true));
+        assert_eq!(ghosts.find(1).map(|ghost| ghost.score), Some(40));
+
+        (2..=4).for_each(|seed| {
+            ghosts.offer(ghost(seed, 10), true);
+        });
+        assert_eq!(ghosts.find(1), None);
+        assert!(ghosts.find(4).is_some());
+
+        let stored = ghost(u64::MAX, 10);
+        let json = serde_json::to_string(&stored).unwrap();
+        assert!(json.contains(r#""seed":"18446744073709551615""#));
+        assert_eq!(serde_json::from_str::<Ghost>(&json).unwrap(), stored);
+    }
+
+    #[test]
+    fn endless_runs_are_raced_again_but_never_saved() {
+        let mut ghosts = Ghosts::default();
+        assert!(!ghosts.offer(ghost(1, 30), false));
+        ghosts.offer(ghost(1, 20), false);
+        assert_eq!(ghosts.find(1).map(|ghost| ghost.score), Some(30));
+
+        ghosts.offer(ghost(2, 10), false);
+        assert_eq!(ghosts.find(1), None);
+        assert!(ghosts.find(2).is_some());
+        assert_eq!(serde_json::to_string(&ghosts).unwrap(), r#"{"runs":[]}"#);
+    }
+}
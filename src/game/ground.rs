@@ -0,0 +1,137 @@
+use crate::engine::{Rect, Renderer, SpriteSheet};
+
+use super::GROUND;
+
+const LEFT_CAP: &str = "1.png";
+const MIDDLE: &str = "2.png";
+const RIGHT_CAP: &str = "7.png";
+const SCREEN_WIDTH: i16 = 600;
+// A whole number of middle tiles, so pulling a run's left edge forward by
+// this much doesn't move the tiles that are drawn.
+const REWIND: i16 = 1024;
+
+// The floor the boy runs along, kept as runs of tiles from `left` up to
+// `right` with pits in between. A run is drawn as a left cap, as many
+// middle tiles as fit and a right cap lined up with its right edge.
+#[derive(Default)]
+pub struct Ground {
+    runs: Vec<(i16, i16)>,
+    end: i16,
+}
+
+impl Ground {
+    pub fn new(left: i16) -> Self {
+        Ground {
+            runs: vec![],
+            end: left,
+        }
+    }
+
+    pub fn extend_to(&mut self, x: i16) {
+        if x <= self.end {
+            return;
+        }
+        match self.runs.last_mut() {
+            Some((_, right)) if *right == self.end => *right = x,
+            _ => self.runs.push((self.end, x)),
+        }
+        self.end = x;
+    }
+
+    pub fn dig(&mut self, left: i16, right: i16) {
+        self.extend_to(right);
+        self.runs = self
+            .runs
+            .iter()
+            .flat_map(|&(run_left, run_right)| {
+                [
+                    (run_left, run_right.min(left)),
+                    (run_left.max(right), run_right),
+                ]
+            })
+            .filter(|(run_left, run_right)| run_left < run_right)
+            .collect();
+    }
+
+    // The boy only falls once none of him is over solid ground.
+    pub fn supports(&self, bounding_box: &Rect) -> bool {
+        self.runs
+            .iter()
+            .any(|&(left, right)| left < bounding_box.right() && right > bounding_box.x())
+    }
+
+    pub fn move_horizontally(&mut self, x: i16) {
+        self.runs.iter_mut().for_each(|(left, right)| {
+            *left += x;
+            *right += x;
+            // The run under the boy can go on for ever, so keep its left
+            // edge well off screen without letting it overflow.
+            while *left < -2 * REWIND {
+                *left += REWIND;
+            }
+        });
+        self.end += x;
+        self.runs.retain(|&(_, right)| right > 0);
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sheet: &SpriteSheet) {
+        self.runs
+            .iter()
+            .filter(|&&(left, _)| left < SCREEN_WIDTH)
+            .for_each(|&(left, right)| draw_run(renderer, sheet, left, right));
+    }
+}
+
+fn draw_run(renderer: &Renderer, sheet: &SpriteSheet, left: i16, right: i16) {
+    let (Some(left_cap), Some(middle), Some(right_cap)) = (
+        sheet.cell(LEFT_CAP),
+        sheet.cell(MIDDLE),
+        sheet.cell(RIGHT_CAP),
+    ) else {
+        return;
+    };
+    let right_cap_x = right - right_cap.frame.w;
+    let middles = (left + left_cap.frame.w..right_cap_x)
+        .step_by(middle.frame.w.max(1) as usize)
+        .map(|x| (middle, x));
+    std::iter::once((left_cap, left))
+        .chain(middles)
+        .chain(std::iter::once((right_cap, right_cap_x)))
+        .filter(|(cell, x)| x + cell.frame.w > 0 && *x < SCREEN_WIDTH)
+        .for_each(|(cell, x)| {
+            sheet.draw(
+                renderer,
+                &Rect::new_from_x_y(cell.frame.x, cell.frame.y, cell.frame.w, cell.frame.h),
+                &Rect::new_from_x_y(x, GROUND, cell.frame.w, cell.frame.h),
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pits_split_the_ground_and_scroll_away() {
+        let mut ground = Ground::new(-100);
+        ground.extend_to(1000);
+        ground.dig(300, 450);
+        ground.dig(1200, 1300);
+        assert_eq!(ground.runs, vec![(-100, 300), (450, 1200)]);
+
+        let over_pit = Rect::new_from_x_y(320, 400, 100, 100);
+        let on_edge = Rect::new_from_x_y(250, 400, 100, 100);
+        assert!(!ground.supports(&over_pit));
+        assert!(ground.supports(&on_edge));
+
+        ground.move_horizontally(-400);
+        ground.extend_to(1000);
+        assert_eq!(ground.runs, vec![(50, 800), (900, 1000)]);
+
+        (0..4).for_each(|_| {
+            ground.move_horizontally(-900);
+            ground.extend_to(1000);
+        });
+        assert_eq!(ground.runs, vec![(-1676, 1000)]);
+    }
+}
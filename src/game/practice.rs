@@ -0,0 +1,216 @@
+use std::rc::Rc;
+
+use super::{title::Title, Walk, WalkTheDogState, WalkTheDogStateMachine, HEIGHT};
+use crate::engine::{KeyState, Point, Rect, Renderer};
+
+const WIDTH: i16 = 600;
+// Each press of the slow motion key steps down to the next speed, then
+// back to full speed.
+const TIME_SCALES: [f32; 3] = [1.0, 0.5, 0.25];
+const SLOW_MOTION_KEY: &str = "KeyM";
+const HEADING_POSITION: Point = Point { x: 300, y: 150 };
+const HEADING_FONT: &str = "30pt 'Ken Future'";
+const CHOICE_POSITION: Point = Point { x: 300, y: 260 };
+const CHOICE_FONT: &str = "20pt 'Ken Future'";
+const TEXT_FONT: &str = "14pt 'Ken Future'";
+const TEXT_COLOR: &str = "white";
+const SELECTED_COLOR: &str = "gold";
+const SHADE_COLOR: &str = "rgba(0, 0, 0, 0.45)";
+const FIRST_DETAIL_Y: i16 = 310;
+const FIRST_HINT_Y: i16 = 480;
+const LINE_HEIGHT: i16 = 32;
+const HUD_POSITION: Point = Point { x: 20, y: 110 };
+const HUD_LINE_HEIGHT: i16 = 35;
+
+// Runs a single segment over and over. A knock-out starts it again straight
+// away rather than ending the run, and it can be slowed down to study.
+pub struct Practice {
+    // The segment picked, as its place in the segment set.
+    selected: usize,
+    // Whether the picked segment is being run rather than chosen.
+    running: bool,
+    attempts: u32,
+    time_scale: usize,
+}
+
+pub enum PracticeEndState {
+    Continue(WalkTheDogState<Practice>),
+    Menu(WalkTheDogState<Title>),
+}
+
+impl From<PracticeEndState> for WalkTheDogStateMachine {
+    fn from(state: PracticeEndState) -> Self {
+        match state {
+            PracticeEndState::Continue(practice) => practice.into(),
+            PracticeEndState::Menu(title) => title.into(),
+        }
+    }
+}
+
+impl WalkTheDogState<Practice> {
+    pub(super) fn new(walk: Walk) -> Self {
+        WalkTheDogState {
+            _state: Practice {
+                selected: 0,
+                running: false,
+                attempts: 0,
+                time_scale: 0,
+            },
+            walk: Box::new(walk),
+        }
+    }
+
+    // Choosing a segment always happens at full speed.
+    pub(super) fn time_scale(&self) -> f32 {
+        if self._state.running {
+            TIME_SCALES[self._state.time_scale]
+        } else {
+            1.0
+        }
+    }
+
+    pub(super) fn update(mut self, keystate: &KeyState) -> PracticeEndState {
+        if keystate.is_just_pressed(SLOW_MOTION_KEY) {
+            self._state.time_scale = (self._state.time_scale + 1) % TIME_SCALES.len();
+        }
+        if self._state.running {
+            return PracticeEndState::Continue(self.run(keystate));
+        }
+
+        let count = self.walk.segments.iter().count();
+        if keystate.is_just_pressed("ArrowLeft") {
+            self._state.selected = (self._state.selected + count - 1) % count;
+        }
+        if keystate.is_just_pressed("ArrowRight") {
+            self._state.selected = (self._state.selected + 1) % count;
+        }
+        if keystate.is_just_pressed("Enter") || keystate.is_just_pressed("Space") {
+            self._state.attempts = 0;
+            PracticeEndState::Continue(self.restart())
+        } else if keystate.is_just_pressed("Escape") {
+            // The title screen starts the boy over, so any ability the
+            // practice segment needed is gone for whatever is played next.
+            PracticeEndState::Menu(WalkTheDogState::<Title>::new(*self.walk))
+        } else {
+            PracticeEndState::Continue(self)
+        }
+    }
+
+    fn run(mut self, keystate: &KeyState) -> Self {
+        if keystate.is_just_pressed("Escape") {
+            self._state.running = false;
+            return self;
+        }
+        let keys = self.walk.settings.keys.clone();
+        self.walk.update(keystate, &keys);
+        if self.walk.knocked_out() || self.walk.fell_into_pit() {
+            self.restart()
+        } else {
+            self
+        }
+    }
+
+    fn restart(mut self) -> Self {
+        let segments = Rc::clone(&self.walk.segments);
+        if let Some(segment) = segments.iter().nth(self._state.selected) {
+            self._state.running = true;
+            self._state.attempts += 1;
+            self.walk = Box::new(Walk::practice(*self.walk, segment));
+        }
+        self
+    }
+
+    pub(super) fn draw_practice(&self, renderer: &Renderer) {
+        if self._state.running {
+            self.draw_run(renderer);
+        } else {
+            self.draw_choice(renderer);
+        }
+    }
+
+    fn draw_run(&self, renderer: &Renderer) {
+        self.walk.draw(renderer);
+        self.walk.run.toasts.draw(renderer);
+        [
+            format!("Practice {}", self.segment_name()),
+            format!("Attempt {}", self._state.attempts),
+            format!("Speed {}% (M)", self.speed_percent()),
+            "Esc: choose another".to_string(),
+        ]
+        .iter()
+        .zip(0..)
+        .for_each(|(line, index)| {
+            let position = Point {
+                x: HUD_POSITION.x,
+                y: HUD_POSITION.y + index * HUD_LINE_HEIGHT,
+            };
+            if let Err(err) = renderer.draw_text(line, &position) {
+                error!("Could not draw practice details {:#?}", err);
+            }
+        });
+    }
+
+    fn draw_choice(&self, renderer: &Renderer) {
+        self.walk.draw_scene(renderer);
+        renderer.fill_rect(&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT), SHADE_COLOR);
+        draw_text(
+            renderer,
+            "Practice",
+            HEADING_POSITION,
+            HEADING_FONT,
+            TEXT_COLOR,
+        );
+        draw_text(
+            renderer,
+            &format!("< {} >", self.segment_name()),
+            CHOICE_POSITION,
+            CHOICE_FONT,
+            SELECTED_COLOR,
+        );
+        let segment = self.walk.segments.iter().nth(self._state.selected);
+        let details = segment.map_or(vec![], |segment| {
+            let mut details = vec![format!("Difficulty {}", segment.difficulty)];
+            if let Some(ability) = segment.requires {
+                details.push(format!("Needs {:?}", ability));
+            }
+            details
+        });
+        draw_lines(renderer, FIRST_DETAIL_Y, &details);
+        draw_lines(
+            renderer,
+            FIRST_HINT_Y,
+            &[
+                "Left/Right: segment  Enter: start".to_string(),
+                format!("M: speed {}%  Esc: menu", self.speed_percent()),
+            ],
+        );
+    }
+
+    fn segment_name(&self) -> &str {
+        self.walk
+            .segments
+            .iter()
+            .nth(self._state.selected)
+            .map_or("", |segment| segment.name.as_str())
+    }
+
+    fn speed_percent(&self) -> u32 {
+        (TIME_SCALES[self._state.time_scale] * 100.0) as u32
+    }
+}
+
+fn draw_lines(renderer: &Renderer, top: i16, lines: &[String]) {
+    lines.iter().zip(0..).for_each(|(line, index)| {
+        let position = Point {
+            x: WIDTH / 2,
+            y: top + index * LINE_HEIGHT,
+        };
+        draw_text(renderer, line, position, TEXT_FONT, TEXT_COLOR);
+    });
+}
+
+fn draw_text(renderer: &Renderer, text: &str, position: Point, font: &str, color: &str) {
+    if let Err(err) = renderer.draw_centered_text(text, &position, font, color) {
+        error!("Could not draw practice text {:#?}", err);
+    }
+}
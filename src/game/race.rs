@@ -0,0 +1,250 @@
+use rand::{thread_rng, Rng};
+
+use super::{title::Title, Ready, Walk, WalkTheDogState, WalkTheDogStateMachine, HEIGHT};
+use crate::{
+    browser::ui::{Panel, UiEvent},
+    engine::{KeyState, Point, Rect, Renderer},
+    settings::{key_name, KeyBindings},
+};
+
+const WIDTH: i16 = 600;
+// Each player sees the whole height of the course at half size, in a
+// square in the middle of their half of the screen. The bars either side
+// hold their controls and score.
+const VIEW_SCALE: f64 = 0.5;
+const VIEW_SIZE: i16 = 300;
+const VIEW_X: i16 = (WIDTH - VIEW_SIZE) / 2;
+const SIDE_COLOR: &str = "rgb(30, 30, 30)";
+const DIVIDER_COLOR: &str = "white";
+const TEXT_FONT: &str = "11pt 'Ken Future'";
+const TEXT_COLOR: &str = "white";
+const FIRST_LINE_Y: i16 = 60;
+const LINE_HEIGHT: i16 = 30;
+const PLAYER_NAMES: [&str; 2] = ["Player 1", "Player 2"];
+
+// How a race finished: either one player won or both finished together.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Outcome {
+    Winner(usize),
+    DeadHeat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RaceAction {
+    Rematch,
+    Solo,
+    Menu,
+}
+
+// Two players racing on the same course, one on the top half of the screen
+// and one on the bottom. Each starts running with their own run key.
+pub struct Race {
+    rival: Box<Walk>,
+    controls: [KeyBindings; 2],
+    panel: Option<Panel<RaceAction>>,
+}
+
+pub enum RaceEndState {
+    Continue(WalkTheDogState<Race>),
+    Solo(WalkTheDogState<Ready>),
+    Menu(WalkTheDogState<Title>),
+}
+
+impl From<RaceEndState> for WalkTheDogStateMachine {
+    fn from(state: RaceEndState) -> Self {
+        match state {
+            RaceEndState::Continue(race) => race.into(),
+            RaceEndState::Solo(ready) => ready.into(),
+            RaceEndState::Menu(title) => title.into(),
+        }
+    }
+}
+
+impl WalkTheDogState<Race> {
+    pub(super) fn new(walk: Walk) -> Self {
+        let seed = thread_rng().gen();
+        let rival = walk.rival();
+        WalkTheDogState {
+            _state: Race {
+                rival: Box::new(Walk::race(rival, seed)),
+                controls: controls(),
+                panel: None,
+            },
+            walk: Box::new(Walk::race(walk, seed)),
+        }
+    }
+
+    pub(super) fn update(mut self, keystate: &KeyState) -> RaceEndState {
+        if let Some(panel) = self._state.panel.as_mut() {
+            return match panel.next_event() {
+                Some(UiEvent::Clicked(RaceAction::Rematch)) => {
+                    RaceEndState::Continue(WalkTheDogState::<Race>::new(*self.walk))
+                }
+                Some(UiEvent::Clicked(RaceAction::Solo)) => {
+                    RaceEndState::Solo(WalkTheDogState::<Ready>::new(Walk::reset(*self.walk)))
+                }
+                Some(UiEvent::Clicked(RaceAction::Menu)) => {
+                    RaceEndState::Menu(WalkTheDogState::<Title>::new(*self.walk))
+                }
+                _ => RaceEndState::Continue(self),
+            };
+        }
+
+        let [first, second] = &self._state.controls;
+        run(&mut self.walk, keystate, first);
+        run(&mut self._state.rival, keystate, second);
+        let walks = [&self.walk, &self._state.rival];
+        if let Some(outcome) = outcome(
+            walks.map(|walk| walk.reached_goal()),
+            walks.map(|walk| walk.knocked_out() || walk.fell_into_pit()),
+        ) {
+            self._state.panel = Some(self.results(outcome));
+        }
+        RaceEndState::Continue(self)
+    }
+
+    fn results(&self, outcome: Outcome) -> Panel<RaceAction> {
+        let heading = match outcome {
+            Outcome::Winner(player) => format!("{} wins!", PLAYER_NAMES[player]),
+            Outcome::DeadHeat => "Dead heat!".to_string(),
+        };
+        Panel::new()
+            .and_then(|mut panel| {
+                panel.label(&heading)?;
+                [&self.walk, &self._state.rival]
+                    .iter()
+                    .zip(PLAYER_NAMES)
+                    .try_for_each(|(walk, name)| {
+                        panel.label(&format!("{}: {}", name, walk.score()))
+                    })?;
+                panel.button(RaceAction::Rematch, "Rematch")?;
+                panel.button(RaceAction::Solo, "One player")?;
+                panel.button(RaceAction::Menu, "Menu")?;
+                Ok(panel)
+            })
+            .unwrap()
+    }
+
+    pub(super) fn draw_race(&self, renderer: &Renderer) {
+        renderer.fill_rect(&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT), SIDE_COLOR);
+        [&self.walk, &self._state.rival]
+            .iter()
+            .zip(&self._state.controls)
+            .enumerate()
+            .for_each(|(player, (walk, keys))| {
+                let top = player as i16 * VIEW_SIZE;
+                renderer.draw_in_viewport(
+                    &Rect::new_from_x_y(VIEW_X, top, VIEW_SIZE, VIEW_SIZE),
+                    VIEW_SCALE,
+                    |renderer| {
+                        walk.draw_scene(renderer);
+                        walk.run.toasts.draw(renderer);
+                    },
+                );
+                draw_lines(
+                    renderer,
+                    VIEW_X / 2,
+                    top,
+                    &[
+                        PLAYER_NAMES[player].to_string(),
+                        format!("Run: {}", key_name(&keys.run)),
+                        format!("Jump: {}", key_name(&keys.jump)),
+                        format!("Slide: {}", key_name(&keys.slide)),
+                    ],
+                );
+                draw_lines(
+                    renderer,
+                    WIDTH - VIEW_X / 2,
+                    top,
+                    &[
+                        format!("Lives {}", walk.boy.lives()),
+                        format!("Score {}", walk.score()),
+                    ],
+                );
+            });
+        renderer.draw_line(
+            &Point { x: 0, y: VIEW_SIZE },
+            &Point {
+                x: WIDTH,
+                y: VIEW_SIZE,
+            },
+            DIVIDER_COLOR,
+            2.0,
+        );
+    }
+}
+
+// Keeps a player's walk going. Until they press their run key the boy
+// waits at the start, since running only starts him from standing still.
+fn run(walk: &mut Walk, keystate: &KeyState, keys: &KeyBindings) {
+    if keystate.is_pressed(&keys.run) {
+        walk.boy.run_right();
+        walk.dog.run_right();
+    }
+    walk.update(keystate, keys);
+}
+
+// Player one has the left hand on WASD and player two the arrow keys.
+fn controls() -> [KeyBindings; 2] {
+    [
+        KeyBindings {
+            run: "KeyD".into(),
+            jump: "KeyW".into(),
+            slide: "KeyS".into(),
+        },
+        KeyBindings {
+            run: "ArrowRight".into(),
+            jump: "ArrowUp".into(),
+            slide: "ArrowDown".into(),
+        },
+    ]
+}
+
+// Reaching the goal wins outright. Otherwise the race goes to whoever is
+// still running once the other is out.
+fn outcome(reached_goal: [bool; 2], out: [bool; 2]) -> Option<Outcome> {
+    match (reached_goal, out) {
+        ([true, true], _) | ([false, false], [true, true]) => Some(Outcome::DeadHeat),
+        ([true, false], _) | ([false, false], [false, true]) => Some(Outcome::Winner(0)),
+        ([false, true], _) | ([false, false], [true, false]) => Some(Outcome::Winner(1)),
+        ([false, false], [false, false]) => None,
+    }
+}
+
+fn draw_lines(renderer: &Renderer, x: i16, top: i16, lines: &[String]) {
+    lines.iter().zip(0..).for_each(|(line, index)| {
+        let position = Point {
+            x,
+            y: top + FIRST_LINE_Y + index * LINE_HEIGHT,
+        };
+        if let Err(err) = renderer.draw_centered_text(line, &position, TEXT_FONT, TEXT_COLOR) {
+            error!("Could not draw race text {:#?}", err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_goal_wins_and_otherwise_the_last_one_running() {
+        assert_eq!(outcome([false, false], [false, false]), None);
+        assert_eq!(
+            outcome([false, false], [true, false]),
+            Some(Outcome::Winner(1))
+        );
+        assert_eq!(
+            outcome([false, true], [true, false]),
+            Some(Outcome::Winner(1))
+        );
+        assert_eq!(
+            outcome([true, false], [false, true]),
+            Some(Outcome::Winner(0))
+        );
+        assert_eq!(
+            outcome([false, false], [true, true]),
+            Some(Outcome::DeadHeat)
+        );
+    }
+}
@@ -1,24 +1,89 @@
 use rhb_states::*;
+use serde::{Deserialize, Serialize};
 use web_sys::HtmlImageElement;
 
-use crate::engine::{Audio, Cell, Rect, Renderer, Sheet, Sound};
+use crate::engine::{Audio, Cell, Point, Rect, Renderer, Sheet, Sound};
 
 mod rhb_states;
 
+pub use rhb_states::jump_offset;
+
+const GHOST_ALPHA: f64 = 0.4;
+
+#[derive(Clone)]
 pub struct RedHatBoy {
     state_machine: RedHatBoyStateMachine,
     sprite_sheet: Sheet,
     image: HtmlImageElement,
+    abilities: Abilities,
+    // Drawn steadily rather than flickering while invulnerable.
+    reduced_motion: bool,
+    events: Vec<BoyEvent>,
+}
+
+// Something the boy did, kept until whatever tracks his run takes it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoyEvent {
+    Jumped,
+    DoubleJumped,
+    Slid,
+    AirDashed,
+    Hurt,
+    KnockedOut,
+    Collected(u32),
+    LandedOnPlatform,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum Ability {
+    DoubleJump,
+    AirDash,
+    FastFall,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AirAction {
+    Dash,
+    FastFall,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Abilities {
+    pub double_jump: bool,
+    pub air_action: Option<AirAction>,
+}
+
+impl Abilities {
+    pub fn has(&self, ability: Ability) -> bool {
+        match ability {
+            Ability::DoubleJump => self.double_jump,
+            Ability::AirDash => self.air_action == Some(AirAction::Dash),
+            Ability::FastFall => self.air_action == Some(AirAction::FastFall),
+        }
+    }
+
+    // Only one air action can be active, so unlocking one replaces the other.
+    pub fn unlock(&mut self, ability: Ability) {
+        match ability {
+            Ability::DoubleJump => self.double_jump = true,
+            Ability::AirDash => self.air_action = Some(AirAction::Dash),
+            Ability::FastFall => self.air_action = Some(AirAction::FastFall),
+        }
+    }
 }
 
 impl RedHatBoy {
     pub fn reset(boy: Self) -> Self {
-        RedHatBoy::new(
-            boy.sprite_sheet,
-            boy.image,
-            boy.state_machine.context().audio.clone(),
-            boy.state_machine.context().jump_sound.clone(),
-        )
+        RedHatBoy {
+            state_machine: RedHatBoyStateMachine::new(
+                boy.state_machine.context().sound_effects.clone(),
+            ),
+            sprite_sheet: boy.sprite_sheet,
+            image: boy.image,
+            abilities: Abilities::default(),
+            reduced_motion: boy.reduced_motion,
+            events: vec![],
+        }
     }
 
     pub fn knocked_out(&self) -> bool {
@@ -30,48 +95,124 @@ impl RedHatBoy {
         image: HtmlImageElement,
         audio: Audio,
         jump_sound: Sound,
+        double_jump_sound: Sound,
+        abilities: Abilities,
     ) -> Self {
         RedHatBoy {
-            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new(audio, jump_sound)),
+            state_machine: RedHatBoyStateMachine::new(Some(SoundEffects {
+                audio,
+                jump: jump_sound,
+                double_jump: double_jump_sound,
+            })),
             sprite_sheet,
             image,
+            abilities,
+            reduced_motion: false,
+            events: vec![],
         }
     }
 
+    pub fn abilities(&self) -> &Abilities {
+        &self.abilities
+    }
+
+    pub fn unlock(&mut self, ability: Ability) {
+        self.abilities.unlock(ability);
+    }
+
+    pub fn take_events(&mut self) -> Vec<BoyEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     pub fn run_right(&mut self) {
-        self.state_machine = self.state_machine.clone().transition(Event::Run);
+        self.transition(Event::Run);
     }
 
     pub fn slide(&mut self) {
-        self.state_machine = self.state_machine.clone().transition(Event::Slide);
+        self.transition(Event::Slide);
+    }
+
+    // Returns whether he actually left the ground.
+    pub fn jump(&mut self) -> bool {
+        self.transition(Event::Jump)
+    }
+
+    pub fn double_jump(&mut self) -> bool {
+        self.abilities.double_jump && self.transition(Event::DoubleJump)
+    }
+
+    fn transition(&mut self, event: Event) -> bool {
+        let next = self.state_machine.clone().transition(event);
+        self.change_to(next)
+    }
+
+    // Returns whether he moved into another state, noting what he did if so.
+    fn change_to(&mut self, next: RedHatBoyStateMachine) -> bool {
+        let before = std::mem::discriminant(&self.state_machine);
+        self.state_machine = next;
+        let changed = before != std::mem::discriminant(&self.state_machine);
+        if let (true, Some(event)) = (changed, self.state_machine.entered()) {
+            self.events.push(event);
+        }
+        changed
+    }
+
+    pub fn air_action(&mut self) {
+        let event = match self.abilities.air_action {
+            Some(AirAction::Dash) => Event::AirDash,
+            Some(AirAction::FastFall) => Event::FastFall,
+            None => return,
+        };
+        self.transition(event);
+    }
+
+    pub fn hit(&mut self) {
+        self.transition(Event::Hit);
+    }
+
+    pub fn lives(&self) -> u8 {
+        self.state_machine.context().lives
     }
 
-    pub fn jump(&mut self) {
-        self.state_machine = self.state_machine.clone().transition(Event::Jump);
+    pub fn set_lives(&mut self, lives: u8) {
+        self.state_machine.context_mut().lives = lives;
     }
 
-    pub fn knock_out(&mut self) {
-        self.state_machine = self.state_machine.clone().transition(Event::KnockOut);
+    pub fn set_reduced_motion(&mut self, reduced_motion: bool) {
+        self.reduced_motion = reduced_motion;
     }
 
+    pub fn collected(&self) -> u32 {
+        self.state_machine.context().collected
+    }
+
+    pub fn collect(&mut self, points: u32) {
+        self.state_machine.context_mut().collect(points);
+        self.events.push(BoyEvent::Collected(points));
+    }
+
+    // Only platforms land him, so coming down onto one is worth noting.
     pub fn land_on(&mut self, position: i16) {
-        self.state_machine = self.state_machine.clone().transition(Event::Land(position));
+        if self.transition(Event::Land(position)) {
+            self.events.push(BoyEvent::LandedOnPlatform);
+        }
     }
 
     pub fn update(&mut self) {
-        self.state_machine = self.state_machine.clone().update();
+        let next = self.state_machine.clone().update();
+        self.change_to(next);
     }
 
-    fn frame_name(&self) -> String {
-        format!(
-            "{} ({}).png",
-            self.state_machine.frame_name(),
-            (self.state_machine.context().frame / 3) + 1
-        )
+    pub fn set_over_ground(&mut self, over_ground: bool) {
+        self.state_machine.context_mut().over_ground = over_ground;
     }
 
-    fn current_sprite(&self) -> Option<&Cell> {
-        self.sprite_sheet.frames.get(&self.frame_name())
+    pub fn standing_on(&self, top: i16) -> bool {
+        self.state_machine.standing_on(top)
+    }
+
+    pub fn ride(&mut self, delta: Point) {
+        self.state_machine.context_mut().ride(delta);
     }
 
     pub fn pos_y(&self) -> i16 {
@@ -83,34 +224,61 @@ impl RedHatBoy {
     }
 
     pub fn bounding_box(&self) -> Rect {
-        const X_OFFSET: i16 = 18;
-        const Y_OFFSET: i16 = 14;
-        const WIDTH_OFFSET: i16 = 28;
-        let mut bounding_box = self.destination_box();
-        bounding_box.set_x(bounding_box.x() + X_OFFSET);
-        bounding_box.width -= WIDTH_OFFSET;
-        bounding_box.set_y(bounding_box.y() + Y_OFFSET);
-        bounding_box.height -= Y_OFFSET;
-
-        bounding_box
+        self.state_machine.bounding_box(&self.sprite_sheet)
     }
 
     pub fn destination_box(&self) -> Rect {
-        let sprite = self.current_sprite().expect("Cell not found");
-        Rect::new_from_x_y(
-            self.state_machine.context().position.x + sprite.sprite_source_size.x,
-            self.state_machine.context().position.y + sprite.sprite_source_size.y,
-            sprite.frame.w,
-            sprite.frame.h,
-        )
+        self.state_machine.destination_box(&self.sprite_sheet)
+    }
+
+    // Where he holds the leash, roughly at his waist on the trailing side.
+    pub fn hand(&self) -> Point {
+        let bounding_box = self.bounding_box();
+        Point {
+            x: bounding_box.x() + 10,
+            y: bounding_box.y() + bounding_box.height / 2,
+        }
+    }
+
+    pub fn set_running_speed(&mut self, speed: i16) {
+        self.state_machine.context_mut().set_running_speed(speed);
     }
 
     pub fn walking_speed(&self) -> i16 {
         self.state_machine.context().velocity.x
     }
 
+    pub fn sprite_name(&self) -> String {
+        self.state_machine.sprite_name()
+    }
+
+    // Draws a see-through boy as he looked at some other time, with his
+    // sprite's top left at `position`.
+    pub fn draw_ghost(&self, renderer: &Renderer, sprite_name: &str, position: Point) {
+        if let Some(sprite) = self.sprite_sheet.frames.get(sprite_name) {
+            renderer.draw_image_with_alpha(
+                &self.image,
+                &Rect::new_from_x_y(
+                    sprite.frame.x,
+                    sprite.frame.y,
+                    sprite.frame.w,
+                    sprite.frame.h,
+                ),
+                &Rect::new(position, sprite.frame.w, sprite.frame.h),
+                GHOST_ALPHA,
+            );
+        }
+    }
+
     pub fn draw(&self, renderer: &Renderer) {
-        let sprite = self.current_sprite().expect("Cell not found");
+        if !self.reduced_motion && self.state_machine.context().flickering() {
+            return;
+        }
+
+        let sprite = self
+            .state_machine
+            .current_sprite(&self.sprite_sheet)
+            .expect("Cell not found");
 
         renderer.draw_image(
             &self.image,
@@ -130,26 +298,79 @@ pub enum Event {
     Slide,
     Update,
     Jump,
-    KnockOut,
+    DoubleJump,
+    AirDash,
+    FastFall,
+    Hit,
     Land(i16),
 }
 
 #[derive(Clone)]
-enum RedHatBoyStateMachine {
+pub struct SoundEffects {
+    pub audio: Audio,
+    pub jump: Sound,
+    pub double_jump: Sound,
+}
+
+// How far in from the edges of his sprite the boy can be hit. His head is
+// well below the top of the slide sprite, so sliding takes more off the top
+// and lets him pass under overhead obstacles.
+struct Hitbox {
+    left: i16,
+    top: i16,
+    right: i16,
+}
+
+const STANDING_HITBOX: Hitbox = Hitbox {
+    left: 18,
+    top: 14,
+    right: 10,
+};
+const SLIDING_HITBOX: Hitbox = Hitbox {
+    left: 18,
+    top: 40,
+    right: 10,
+};
+
+#[derive(Clone)]
+pub(crate) enum RedHatBoyStateMachine {
     Idle(RedHatBoyState<Idle>),
     Running(RedHatBoyState<Running>),
     Sliding(RedHatBoyState<Sliding>),
     Jumping(RedHatBoyState<Jumping>),
+    DoubleJumping(RedHatBoyState<DoubleJumping>),
+    AirDashing(RedHatBoyState<AirDashing>),
+    Airborne(RedHatBoyState<Airborne>),
+    Hurt(RedHatBoyState<Hurt>),
     Falling(RedHatBoyState<Falling>),
     KnockedOut(RedHatBoyState<KnockedOut>),
 }
 
 impl RedHatBoyStateMachine {
+    // Without sound effects the boy runs silently, which lets the state
+    // machine run outside of a browser.
+    pub(crate) fn new(sound_effects: Option<SoundEffects>) -> Self {
+        RedHatBoyStateMachine::Idle(RedHatBoyState::new(sound_effects))
+    }
+
     fn knocked_out(&self) -> bool {
         matches!(self, RedHatBoyStateMachine::KnockedOut(_))
     }
 
-    fn transition(self, event: Event) -> Self {
+    // What the boy did to end up in this state, for those worth counting.
+    fn entered(&self) -> Option<BoyEvent> {
+        match self {
+            RedHatBoyStateMachine::Jumping(_) => Some(BoyEvent::Jumped),
+            RedHatBoyStateMachine::DoubleJumping(_) => Some(BoyEvent::DoubleJumped),
+            RedHatBoyStateMachine::Sliding(_) => Some(BoyEvent::Slid),
+            RedHatBoyStateMachine::AirDashing(_) => Some(BoyEvent::AirDashed),
+            RedHatBoyStateMachine::Hurt(_) => Some(BoyEvent::Hurt),
+            RedHatBoyStateMachine::KnockedOut(_) => Some(BoyEvent::KnockedOut),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn transition(self, event: Event) -> Self {
         match (self.clone(), event) {
             (RedHatBoyStateMachine::Idle(state), Event::Run) => state.run().into(),
             (RedHatBoyStateMachine::Idle(state), Event::Update) => state.update().into(),
@@ -157,13 +378,14 @@ impl RedHatBoyStateMachine {
             (RedHatBoyStateMachine::Running(state), Event::Slide) => state.slide().into(),
             (RedHatBoyStateMachine::Running(state), Event::Jump) => state.jump().into(),
             (RedHatBoyStateMachine::Running(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Running(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Hit) => state.hit().into(),
             (RedHatBoyStateMachine::Running(state), Event::Land(position)) => {
                 state.land_on(position).into()
             }
 
+            (RedHatBoyStateMachine::Sliding(state), Event::Slide) => state.keep_sliding().into(),
             (RedHatBoyStateMachine::Sliding(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Sliding(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Sliding(state), Event::Hit) => state.hit().into(),
             (RedHatBoyStateMachine::Sliding(state), Event::Land(position)) => {
                 state.land_on(position).into()
             }
@@ -171,10 +393,45 @@ impl RedHatBoyStateMachine {
             (RedHatBoyStateMachine::Falling(state), Event::Update) => state.update().into(),
 
             (RedHatBoyStateMachine::Jumping(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Jumping(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::DoubleJump) => {
+                state.double_jump().into()
+            }
+            (RedHatBoyStateMachine::Jumping(state), Event::AirDash) => state.air_dash().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::FastFall) => state.fast_fall().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::Hit) => state.hit().into(),
             (RedHatBoyStateMachine::Jumping(state), Event::Land(position)) => {
                 state.land_on(position).into()
             }
+
+            (RedHatBoyStateMachine::DoubleJumping(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::DoubleJumping(state), Event::AirDash) => {
+                state.air_dash().into()
+            }
+            (RedHatBoyStateMachine::DoubleJumping(state), Event::FastFall) => {
+                state.fast_fall().into()
+            }
+            (RedHatBoyStateMachine::DoubleJumping(state), Event::Hit) => state.hit().into(),
+            (RedHatBoyStateMachine::DoubleJumping(state), Event::Land(position)) => {
+                state.land_on(position).into()
+            }
+
+            (RedHatBoyStateMachine::AirDashing(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::AirDashing(state), Event::Hit) => state.hit().into(),
+            (RedHatBoyStateMachine::AirDashing(state), Event::Land(position)) => {
+                state.land_on(position).into()
+            }
+
+            (RedHatBoyStateMachine::Airborne(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Airborne(state), Event::FastFall) => state.fast_fall().into(),
+            (RedHatBoyStateMachine::Airborne(state), Event::Hit) => state.hit().into(),
+            (RedHatBoyStateMachine::Airborne(state), Event::Land(position)) => {
+                state.land_on(position).into()
+            }
+
+            (RedHatBoyStateMachine::Hurt(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Hurt(state), Event::Land(position)) => {
+                state.land_on(position).into()
+            }
             _ => self,
         }
     }
@@ -185,25 +442,95 @@ impl RedHatBoyStateMachine {
             RedHatBoyStateMachine::Running(state) => state.frame_name(),
             RedHatBoyStateMachine::Sliding(state) => state.frame_name(),
             RedHatBoyStateMachine::Jumping(state) => state.frame_name(),
+            RedHatBoyStateMachine::DoubleJumping(state) => state.frame_name(),
+            RedHatBoyStateMachine::AirDashing(state) => state.frame_name(),
+            RedHatBoyStateMachine::Airborne(state) => state.frame_name(),
+            RedHatBoyStateMachine::Hurt(state) => state.frame_name(),
             RedHatBoyStateMachine::Falling(state) => state.frame_name(),
             RedHatBoyStateMachine::KnockedOut(state) => state.frame_name(),
         }
     }
 
-    fn context(&self) -> &RedHatBoyContext {
+    pub(crate) fn context(&self) -> &RedHatBoyContext {
         match self {
             RedHatBoyStateMachine::Idle(state) => state.context(),
             RedHatBoyStateMachine::Running(state) => state.context(),
             RedHatBoyStateMachine::Sliding(state) => state.context(),
             RedHatBoyStateMachine::Jumping(state) => state.context(),
+            RedHatBoyStateMachine::DoubleJumping(state) => state.context(),
+            RedHatBoyStateMachine::AirDashing(state) => state.context(),
+            RedHatBoyStateMachine::Airborne(state) => state.context(),
+            RedHatBoyStateMachine::Hurt(state) => state.context(),
             RedHatBoyStateMachine::Falling(state) => state.context(),
             RedHatBoyStateMachine::KnockedOut(state) => state.context(),
         }
     }
 
-    fn update(self) -> Self {
+    pub(crate) fn context_mut(&mut self) -> &mut RedHatBoyContext {
+        match self {
+            RedHatBoyStateMachine::Idle(state) => state.context_mut(),
+            RedHatBoyStateMachine::Running(state) => state.context_mut(),
+            RedHatBoyStateMachine::Sliding(state) => state.context_mut(),
+            RedHatBoyStateMachine::Jumping(state) => state.context_mut(),
+            RedHatBoyStateMachine::DoubleJumping(state) => state.context_mut(),
+            RedHatBoyStateMachine::AirDashing(state) => state.context_mut(),
+            RedHatBoyStateMachine::Airborne(state) => state.context_mut(),
+            RedHatBoyStateMachine::Hurt(state) => state.context_mut(),
+            RedHatBoyStateMachine::Falling(state) => state.context_mut(),
+            RedHatBoyStateMachine::KnockedOut(state) => state.context_mut(),
+        }
+    }
+
+    pub(crate) fn update(self) -> Self {
         self.transition(Event::Update)
     }
+
+    // Landing puts the boy's feet exactly on top of the platform and stops
+    // him falling, which is only true while he stands on it.
+    pub(crate) fn standing_on(&self, top: i16) -> bool {
+        let context = self.context();
+        context.velocity.y == 0 && context.feet() == top
+    }
+
+    fn sprite_name(&self) -> String {
+        format!(
+            "{} ({}).png",
+            self.frame_name(),
+            (self.context().frame / 3) + 1
+        )
+    }
+
+    fn current_sprite<'a>(&self, sprite_sheet: &'a Sheet) -> Option<&'a Cell> {
+        sprite_sheet.frames.get(&self.sprite_name())
+    }
+
+    fn hitbox(&self) -> &Hitbox {
+        match self {
+            RedHatBoyStateMachine::Sliding(_) => &SLIDING_HITBOX,
+            _ => &STANDING_HITBOX,
+        }
+    }
+
+    pub(crate) fn bounding_box(&self, sprite_sheet: &Sheet) -> Rect {
+        let hitbox = self.hitbox();
+        let mut bounding_box = self.destination_box(sprite_sheet);
+        bounding_box.set_x(bounding_box.x() + hitbox.left);
+        bounding_box.width -= hitbox.left + hitbox.right;
+        bounding_box.set_y(bounding_box.y() + hitbox.top);
+        bounding_box.height -= hitbox.top;
+
+        bounding_box
+    }
+
+    fn destination_box(&self, sprite_sheet: &Sheet) -> Rect {
+        let sprite = self.current_sprite(sprite_sheet).expect("Cell not found");
+        Rect::new_from_x_y(
+            self.context().position.x + sprite.sprite_source_size.x,
+            self.context().position.y + sprite.sprite_source_size.y,
+            sprite.frame.w,
+            sprite.frame.h,
+        )
+    }
 }
 
 impl From<RedHatBoyState<Idle>> for RedHatBoyStateMachine {
@@ -230,6 +557,30 @@ impl From<RedHatBoyState<Jumping>> for RedHatBoyStateMachine {
     }
 }
 
+impl From<RedHatBoyState<DoubleJumping>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<DoubleJumping>) -> Self {
+        RedHatBoyStateMachine::DoubleJumping(state)
+    }
+}
+
+impl From<RedHatBoyState<AirDashing>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<AirDashing>) -> Self {
+        RedHatBoyStateMachine::AirDashing(state)
+    }
+}
+
+impl From<RedHatBoyState<Airborne>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Airborne>) -> Self {
+        RedHatBoyStateMachine::Airborne(state)
+    }
+}
+
+impl From<RedHatBoyState<Hurt>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Hurt>) -> Self {
+        RedHatBoyStateMachine::Hurt(state)
+    }
+}
+
 impl From<RedHatBoyState<Falling>> for RedHatBoyStateMachine {
     fn from(state: RedHatBoyState<Falling>) -> Self {
         RedHatBoyStateMachine::Falling(state)
@@ -242,20 +593,64 @@ impl From<RedHatBoyState<KnockedOut>> for RedHatBoyStateMachine {
     }
 }
 
+impl From<RunningEndState> for RedHatBoyStateMachine {
+    fn from(end_state: RunningEndState) -> Self {
+        match end_state {
+            RunningEndState::Running(running_state) => running_state.into(),
+            RunningEndState::Airborne(airborne_state) => airborne_state.into(),
+        }
+    }
+}
+
 impl From<SlidingEndState> for RedHatBoyStateMachine {
     fn from(end_state: SlidingEndState) -> Self {
         match end_state {
             SlidingEndState::Complete(running_state) => running_state.into(),
             SlidingEndState::Sliding(sliding_state) => sliding_state.into(),
+            SlidingEndState::Airborne(airborne_state) => airborne_state.into(),
+        }
+    }
+}
+
+impl From<AirDashingEndState> for RedHatBoyStateMachine {
+    fn from(end_state: AirDashingEndState) -> Self {
+        match end_state {
+            AirDashingEndState::Complete(airborne_state) => airborne_state.into(),
+            AirDashingEndState::AirDashing(air_dashing_state) => air_dashing_state.into(),
+        }
+    }
+}
+
+impl From<HurtEndState> for RedHatBoyStateMachine {
+    fn from(end_state: HurtEndState) -> Self {
+        match end_state {
+            HurtEndState::Complete(running_state) => running_state.into(),
+            HurtEndState::Hurt(hurt_state) => hurt_state.into(),
         }
     }
 }
 
-impl From<JumpingEndState> for RedHatBoyStateMachine {
-    fn from(end_state: JumpingEndState) -> Self {
+impl<S> From<HitEndState<S>> for RedHatBoyStateMachine
+where
+    RedHatBoyState<S>: Into<RedHatBoyStateMachine>,
+{
+    fn from(end_state: HitEndState<S>) -> Self {
         match end_state {
-            JumpingEndState::Complete(running_state) => running_state.into(),
-            JumpingEndState::Jumping(jumping_state) => jumping_state.into(),
+            HitEndState::Unharmed(unharmed_state) => unharmed_state.into(),
+            HitEndState::Hurt(hurt_state) => hurt_state.into(),
+            HitEndState::KnockedOut(falling_state) => falling_state.into(),
+        }
+    }
+}
+
+impl<S> From<LandingEndState<S>> for RedHatBoyStateMachine
+where
+    RedHatBoyState<S>: Into<RedHatBoyStateMachine>,
+{
+    fn from(end_state: LandingEndState<S>) -> Self {
+        match end_state {
+            LandingEndState::Complete(running_state) => running_state.into(),
+            LandingEndState::InAir(in_air_state) => in_air_state.into(),
         }
     }
 }
@@ -268,3 +663,57 @@ impl From<FallingEndState> for RedHatBoyStateMachine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> Sheet {
+        let json = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/static/rhb.json"))
+            .unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn running_off_a_platform_falls_until_he_lands() {
+        let sheet = sheet();
+        let floor = RedHatBoyStateMachine::new(None).context().position.y;
+
+        let mut boy = RedHatBoyStateMachine::new(None)
+            .transition(Event::Run)
+            .transition(Event::Land(400));
+        assert!(matches!(boy, RedHatBoyStateMachine::Running(_)));
+        // Nothing lands him again once he has run past the platform's edge.
+        while matches!(boy, RedHatBoyStateMachine::Running(_)) {
+            boy = boy.update();
+        }
+        assert!(matches!(boy, RedHatBoyStateMachine::Airborne(_)));
+        while matches!(boy, RedHatBoyStateMachine::Airborne(_)) {
+            assert!(boy.sprite_name().starts_with("Jump"));
+            assert!(boy.current_sprite(&sheet).is_some());
+            boy = boy.update();
+        }
+        assert!(matches!(boy, RedHatBoyStateMachine::Running(_)));
+        assert_eq!(boy.context().position.y, floor);
+    }
+
+    #[test]
+    fn falling_onto_a_lower_platform_lands_him_there() {
+        let on_lower_platform = RedHatBoyStateMachine::new(None)
+            .transition(Event::Run)
+            .transition(Event::Land(400));
+        let mut boy = RedHatBoyStateMachine::new(None)
+            .transition(Event::Run)
+            .transition(Event::Land(300));
+        while !matches!(boy, RedHatBoyStateMachine::Airborne(_)) {
+            boy = boy.update();
+        }
+
+        let boy = boy.transition(Event::Land(400));
+        assert!(matches!(boy, RedHatBoyStateMachine::Running(_)));
+        assert_eq!(
+            boy.context().position.y,
+            on_lower_platform.context().position.y
+        );
+    }
+}
@@ -1,26 +1,50 @@
-use crate::{
-    engine::{Audio, Point, Sound},
-    game::HEIGHT,
-};
+use super::SoundEffects;
+use crate::{engine::Point, game::GROUND};
 
-const FLOOR: i16 = 479;
-const PLAYER_HEIGHT: i16 = HEIGHT - FLOOR;
+const PLAYER_HEIGHT: i16 = 121;
+const FLOOR: i16 = GROUND - PLAYER_HEIGHT;
 const STARTING_POINT: i16 = -20;
 const RUNNING_SPEED: i16 = 4;
 const JUMP_SPEED: i16 = -25;
+const DOUBLE_JUMP_SPEED: i16 = -20;
+const FAST_FALL_SPEED: i16 = 15;
+const AIR_DASH_SPEED: i16 = 8;
+const KNOCKBACK_SPEED: i16 = -3;
+const KNOCKBACK_HOP: i16 = -8;
+const STARTING_LIVES: u8 = 3;
+const INVULNERABLE_FRAMES: u8 = 90;
+const FLICKER_FRAMES: u8 = 4;
 const GRAVITY: i16 = 1;
 const TERMINAL_VELOCITY: i16 = 20;
 
 const IDLE_FRAMES: u8 = 29;
 const RUNNING_FRAMES: u8 = 23;
 const SLIDING_FRAMES: u8 = 14;
+const SLIDE_HOLD_FRAME: u8 = 12;
 const JUMPING_FRAMES: u8 = 35;
 const FALLING_FRAMES: u8 = 29;
+const AIR_DASH_FRAMES: u8 = 14;
+const HURT_FRAMES: u8 = 23;
+// The sheet has no falling artwork, so the boy drops with the last few
+// frames of his jump, where he is already on the way down.
+const AIRBORNE_FIRST_FRAME: u8 = 24;
 const IDLE_FRAME_NAME: &str = "Idle";
 const RUN_FRAME_NAME: &str = "Run";
 const FALLING_FRAME_NAME: &str = "Dead";
 const SLIDING_FRAME_NAME: &str = "Slide";
 const JUMPING_FRAME_NAME: &str = "Jump";
+const AIRBORNE_FRAME_NAME: &str = JUMPING_FRAME_NAME;
+const AIR_DASH_FRAME_NAME: &str = "Slide";
+const HURT_FRAME_NAME: &str = "Hurt";
+
+// Where a jump from the floor takes the boy after `frames` frames at the
+// starting running speed, for laying things out along the jump curve.
+pub fn jump_offset(frames: i16) -> Point {
+    Point {
+        x: RUNNING_SPEED * frames,
+        y: JUMP_SPEED * frames + GRAVITY * frames * (frames + 1) / 2,
+    }
+}
 
 #[derive(Clone)]
 pub struct RedHatBoyState<S> {
@@ -33,9 +57,75 @@ impl<S> RedHatBoyState<S> {
         &self.context
     }
 
+    pub fn context_mut(&mut self) -> &mut RedHatBoyContext {
+        &mut self.context
+    }
+
     pub fn update_context(&mut self, frames: u8) {
         self.context = self.context.clone().update(frames);
     }
+
+    fn update_in_air(mut self, frames: u8) -> LandingEndState<S> {
+        self.update_context(frames);
+
+        if self.context.on_floor() {
+            LandingEndState::Complete(self.land(GROUND))
+        } else {
+            LandingEndState::InAir(self)
+        }
+    }
+
+    fn land(self, position: i16) -> RedHatBoyState<Running> {
+        RedHatBoyState {
+            context: self.context.reset_frame().set_on(position),
+            _state: Running,
+        }
+    }
+
+    fn unsupported(&self) -> bool {
+        self.context.velocity.y > GRAVITY && self.context.position.y != FLOOR
+    }
+
+    fn drop_off(self) -> RedHatBoyState<Airborne> {
+        RedHatBoyState {
+            context: self.context.set_frame(AIRBORNE_FIRST_FRAME),
+            _state: Airborne,
+        }
+    }
+
+    fn take_hit(self) -> HitEndState<S> {
+        if self.context.invulnerable() {
+            HitEndState::Unharmed(self)
+        } else if self.context.lives <= 1 {
+            HitEndState::KnockedOut(RedHatBoyState {
+                context: self.context.lose_life().reset_frame().stop(),
+                _state: Falling,
+            })
+        } else {
+            HitEndState::Hurt(RedHatBoyState {
+                context: self.context.lose_life().reset_frame().knock_back(),
+                _state: Hurt,
+            })
+        }
+    }
+
+    fn fall_faster(self) -> Self {
+        RedHatBoyState {
+            context: self.context.set_vertical_velocity(FAST_FALL_SPEED),
+            _state: self._state,
+        }
+    }
+}
+
+pub enum LandingEndState<S> {
+    Complete(RedHatBoyState<Running>),
+    InAir(RedHatBoyState<S>),
+}
+
+pub enum HitEndState<S> {
+    Unharmed(RedHatBoyState<S>),
+    Hurt(RedHatBoyState<Hurt>),
+    KnockedOut(RedHatBoyState<Falling>),
 }
 
 #[derive(Clone)]
@@ -43,8 +133,12 @@ pub struct RedHatBoyContext {
     pub frame: u8,
     pub position: Point,
     pub velocity: Point,
-    pub audio: Audio,
-    pub jump_sound: Sound,
+    pub sound_effects: Option<SoundEffects>,
+    pub lives: u8,
+    pub collected: u32,
+    pub over_ground: bool,
+    pub invulnerable_frames: u8,
+    pub running_speed: i16,
 }
 
 impl RedHatBoyContext {
@@ -59,22 +153,95 @@ impl RedHatBoyContext {
             self.frame = 0;
         }
 
+        self.invulnerable_frames = self.invulnerable_frames.saturating_sub(1);
+
+        // Only stop on the floor when coming down onto it, so once the boy
+        // has dropped into a pit he can't pop back up onto the far side.
+        let above_floor = self.position.y <= FLOOR;
         self.position.y += self.velocity.y;
 
-        if self.position.y > FLOOR {
+        if self.over_ground && above_floor && self.position.y > FLOOR {
             self.position.y = FLOOR;
+            self.velocity.y = 0;
         }
 
         self
     }
 
-    fn reset_frame(mut self) -> Self {
-        self.frame = 0;
+    fn on_floor(&self) -> bool {
+        self.position.y == FLOOR && self.velocity.y == 0
+    }
+
+    pub fn feet(&self) -> i16 {
+        self.position.y + PLAYER_HEIGHT
+    }
+
+    // Carries the boy along with whatever he is standing on.
+    pub fn ride(&mut self, delta: Point) {
+        self.position.x += delta.x;
+        self.position.y += delta.y;
+    }
+
+    fn invulnerable(&self) -> bool {
+        self.invulnerable_frames > 0
+    }
+
+    // Alternates between drawn and hidden every few frames while invulnerable.
+    pub fn flickering(&self) -> bool {
+        self.invulnerable() && (self.invulnerable_frames / FLICKER_FRAMES) % 2 == 1
+    }
+
+    pub fn collect(&mut self, points: u32) {
+        self.collected += points;
+    }
+
+    fn lose_life(mut self) -> Self {
+        self.lives = self.lives.saturating_sub(1);
+        self
+    }
+
+    fn knock_back(mut self) -> Self {
+        self.velocity.x = KNOCKBACK_SPEED;
+        self.velocity.y = KNOCKBACK_HOP;
+        self.invulnerable_frames = INVULNERABLE_FRAMES;
+        self
+    }
+
+    fn recover(mut self) -> Self {
+        self.velocity.x = self.running_speed;
+        self
+    }
+
+    fn reset_frame(self) -> Self {
+        self.set_frame(0)
+    }
+
+    fn set_frame(mut self, frame: u8) -> Self {
+        self.frame = frame;
         self
     }
 
     fn run_right(mut self) -> Self {
-        self.velocity.x += RUNNING_SPEED;
+        self.velocity.x += self.running_speed;
+        self
+    }
+
+    // Only adjusts the current velocity while moving forwards, so states that
+    // are stopped or knocked back pick the new speed up when they next run.
+    pub fn set_running_speed(&mut self, speed: i16) {
+        if self.velocity.x > 0 {
+            self.velocity.x += speed - self.running_speed;
+        }
+        self.running_speed = speed;
+    }
+
+    fn dash(mut self) -> Self {
+        self.velocity.x += AIR_DASH_SPEED;
+        self
+    }
+
+    fn end_dash(mut self) -> Self {
+        self.velocity.x -= AIR_DASH_SPEED;
         self
     }
 
@@ -92,12 +259,24 @@ impl RedHatBoyContext {
     fn set_on(mut self, position: i16) -> Self {
         let position = position - PLAYER_HEIGHT;
         self.position.y = position;
+        self.velocity.y = 0;
         self
     }
 
     fn play_jump_sound(self) -> Self {
-        if let Err(err) = self.audio.play_sound(&self.jump_sound) {
-            log!("Error playing jump sound {:#?}", err);
+        if let Some(sound_effects) = &self.sound_effects {
+            if let Err(err) = sound_effects.audio.play_sound(&sound_effects.jump) {
+                log!("Error playing jump sound {:#?}", err);
+            }
+        }
+        self
+    }
+
+    fn play_double_jump_sound(self) -> Self {
+        if let Some(sound_effects) = &self.sound_effects {
+            if let Err(err) = sound_effects.audio.play_sound(&sound_effects.double_jump) {
+                log!("Error playing double jump sound {:#?}", err);
+            }
         }
         self
     }
@@ -116,7 +295,7 @@ impl RedHatBoyState<Idle> {
         self
     }
 
-    pub fn new(audio: Audio, jump_sound: Sound) -> Self {
+    pub fn new(sound_effects: Option<SoundEffects>) -> Self {
         RedHatBoyState {
             context: RedHatBoyContext {
                 frame: 0,
@@ -125,8 +304,12 @@ impl RedHatBoyState<Idle> {
                     y: FLOOR,
                 },
                 velocity: Point { x: 0, y: 0 },
-                audio,
-                jump_sound,
+                sound_effects,
+                lives: STARTING_LIVES,
+                collected: 0,
+                over_ground: true,
+                invulnerable_frames: 0,
+                running_speed: RUNNING_SPEED,
             },
             _state: Idle {},
         }
@@ -143,14 +326,24 @@ impl RedHatBoyState<Idle> {
 #[derive(Copy, Clone)]
 pub struct Running;
 
+pub enum RunningEndState {
+    Running(RedHatBoyState<Running>),
+    Airborne(RedHatBoyState<Airborne>),
+}
+
 impl RedHatBoyState<Running> {
     pub fn frame_name(&self) -> &str {
         RUN_FRAME_NAME
     }
 
-    pub fn update(mut self) -> Self {
+    pub fn update(mut self) -> RunningEndState {
         self.update_context(RUNNING_FRAMES);
-        self
+
+        if self.unsupported() {
+            RunningEndState::Airborne(self.drop_off())
+        } else {
+            RunningEndState::Running(self)
+        }
     }
 
     pub fn slide(self) -> RedHatBoyState<Sliding> {
@@ -160,11 +353,8 @@ impl RedHatBoyState<Running> {
         }
     }
 
-    pub fn knock_out(self) -> RedHatBoyState<Falling> {
-        RedHatBoyState {
-            context: self.context.reset_frame().stop(),
-            _state: Falling {},
-        }
+    pub fn hit(self) -> HitEndState<Running> {
+        self.take_hit()
     }
 
     pub fn jump(self) -> RedHatBoyState<Jumping> {
@@ -192,6 +382,7 @@ pub struct Sliding;
 pub enum SlidingEndState {
     Complete(RedHatBoyState<Running>),
     Sliding(RedHatBoyState<Sliding>),
+    Airborne(RedHatBoyState<Airborne>),
 }
 
 impl RedHatBoyState<Sliding> {
@@ -202,13 +393,21 @@ impl RedHatBoyState<Sliding> {
     pub fn update(mut self) -> SlidingEndState {
         self.update_context(SLIDING_FRAMES);
 
-        if self.context.frame >= SLIDING_FRAMES {
+        if self.unsupported() {
+            SlidingEndState::Airborne(self.drop_off())
+        } else if self.context.frame >= SLIDING_FRAMES {
             SlidingEndState::Complete(self.stand())
         } else {
             SlidingEndState::Sliding(self)
         }
     }
 
+    // Holding the slide keeps him in its last pose instead of standing up.
+    pub fn keep_sliding(mut self) -> Self {
+        self.context.frame = self.context.frame.min(SLIDE_HOLD_FRAME);
+        self
+    }
+
     pub fn stand(self) -> RedHatBoyState<Running> {
         RedHatBoyState {
             context: self.context.reset_frame(),
@@ -223,48 +422,196 @@ impl RedHatBoyState<Sliding> {
         }
     }
 
-    pub fn knock_out(self) -> RedHatBoyState<Falling> {
-        RedHatBoyState {
-            context: self.context.reset_frame().stop(),
-            _state: Falling {},
-        }
+    pub fn hit(self) -> HitEndState<Sliding> {
+        self.take_hit()
     }
 }
 
 #[derive(Copy, Clone)]
 pub struct Jumping;
 
-pub enum JumpingEndState {
-    Complete(RedHatBoyState<Running>),
-    Jumping(RedHatBoyState<Jumping>),
+impl RedHatBoyState<Jumping> {
+    pub fn frame_name(&self) -> &str {
+        JUMPING_FRAME_NAME
+    }
+
+    pub fn update(self) -> LandingEndState<Jumping> {
+        self.update_in_air(JUMPING_FRAMES)
+    }
+
+    pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
+        self.land(position)
+    }
+
+    pub fn double_jump(self) -> RedHatBoyState<DoubleJumping> {
+        RedHatBoyState {
+            context: self
+                .context
+                .set_vertical_velocity(DOUBLE_JUMP_SPEED)
+                .reset_frame()
+                .play_double_jump_sound(),
+            _state: DoubleJumping,
+        }
+    }
+
+    pub fn air_dash(self) -> RedHatBoyState<AirDashing> {
+        RedHatBoyState {
+            context: self.context.reset_frame().dash(),
+            _state: AirDashing,
+        }
+    }
+
+    pub fn fast_fall(self) -> Self {
+        self.fall_faster()
+    }
+
+    pub fn hit(self) -> HitEndState<Jumping> {
+        self.take_hit()
+    }
 }
 
-impl RedHatBoyState<Jumping> {
+#[derive(Copy, Clone)]
+pub struct DoubleJumping;
+
+impl RedHatBoyState<DoubleJumping> {
     pub fn frame_name(&self) -> &str {
         JUMPING_FRAME_NAME
     }
 
-    pub fn update(mut self) -> JumpingEndState {
-        self.update_context(JUMPING_FRAMES);
+    pub fn update(self) -> LandingEndState<DoubleJumping> {
+        self.update_in_air(JUMPING_FRAMES)
+    }
+
+    pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
+        self.land(position)
+    }
+
+    pub fn air_dash(self) -> RedHatBoyState<AirDashing> {
+        RedHatBoyState {
+            context: self.context.reset_frame().dash(),
+            _state: AirDashing,
+        }
+    }
+
+    pub fn fast_fall(self) -> Self {
+        self.fall_faster()
+    }
+
+    pub fn hit(self) -> HitEndState<DoubleJumping> {
+        self.take_hit()
+    }
+}
 
-        if self.context.position.y >= FLOOR {
-            JumpingEndState::Complete(self.land_on(HEIGHT))
+#[derive(Copy, Clone)]
+pub struct AirDashing;
+
+pub enum AirDashingEndState {
+    Complete(RedHatBoyState<Airborne>),
+    AirDashing(RedHatBoyState<AirDashing>),
+}
+
+impl RedHatBoyState<AirDashing> {
+    pub fn frame_name(&self) -> &str {
+        AIR_DASH_FRAME_NAME
+    }
+
+    pub fn update(mut self) -> AirDashingEndState {
+        // Cancel out gravity so the dash travels in a straight line.
+        self.context = self.context.set_vertical_velocity(-GRAVITY);
+        self.update_context(AIR_DASH_FRAMES);
+
+        if self.context.frame >= AIR_DASH_FRAMES {
+            AirDashingEndState::Complete(self.finish_dash())
         } else {
-            JumpingEndState::Jumping(self)
+            AirDashingEndState::AirDashing(self)
+        }
+    }
+
+    pub fn finish_dash(self) -> RedHatBoyState<Airborne> {
+        RedHatBoyState {
+            context: self.context.end_dash(),
+            _state: Airborne,
         }
+        .drop_off()
     }
 
     pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
         RedHatBoyState {
-            context: self.context.reset_frame().set_on(position),
+            context: self.context.end_dash(),
+            _state: AirDashing,
+        }
+        .land(position)
+    }
+
+    pub fn hit(self) -> HitEndState<AirDashing> {
+        self.take_hit()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Airborne;
+
+impl RedHatBoyState<Airborne> {
+    pub fn frame_name(&self) -> &str {
+        AIRBORNE_FRAME_NAME
+    }
+
+    pub fn update(self) -> LandingEndState<Airborne> {
+        match self.update_in_air(JUMPING_FRAMES) {
+            LandingEndState::InAir(airborne) if airborne.context.frame < AIRBORNE_FIRST_FRAME => {
+                LandingEndState::InAir(airborne.drop_off())
+            }
+            end_state => end_state,
+        }
+    }
+
+    pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
+        self.land(position)
+    }
+
+    pub fn fast_fall(self) -> Self {
+        self.fall_faster()
+    }
+
+    pub fn hit(self) -> HitEndState<Airborne> {
+        self.take_hit()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Hurt;
+
+pub enum HurtEndState {
+    Complete(RedHatBoyState<Running>),
+    Hurt(RedHatBoyState<Hurt>),
+}
+
+impl RedHatBoyState<Hurt> {
+    pub fn frame_name(&self) -> &str {
+        HURT_FRAME_NAME
+    }
+
+    pub fn update(mut self) -> HurtEndState {
+        self.update_context(HURT_FRAMES);
+
+        if self.context.frame >= HURT_FRAMES {
+            HurtEndState::Complete(self.recover())
+        } else {
+            HurtEndState::Hurt(self)
+        }
+    }
+
+    pub fn recover(self) -> RedHatBoyState<Running> {
+        RedHatBoyState {
+            context: self.context.reset_frame().recover(),
             _state: Running,
         }
     }
 
-    pub fn knock_out(self) -> RedHatBoyState<Falling> {
+    pub fn land_on(self, position: i16) -> RedHatBoyState<Hurt> {
         RedHatBoyState {
-            context: self.context.reset_frame().stop(),
-            _state: Falling {},
+            context: self.context.set_on(position),
+            _state: Hurt,
         }
     }
 }
@@ -0,0 +1,569 @@
+// Checks that a segment can be cleared by running the real RedHatBoy state
+// machine against its obstacles and searching over every jump and slide
+// timing, so designers find out about impossible segments from `cargo test`
+// rather than from players.
+use std::collections::HashSet;
+use std::mem::{discriminant, Discriminant};
+
+use super::rhb::{AirAction, Event, RedHatBoyStateMachine};
+use super::{lay_out_tiles, tile_bounding_box, Abilities, GROUND};
+use crate::engine::{Point, Rect, Sheet};
+use crate::segments::{PlatformPath, SegmentDefinition, SegmentSet};
+
+enum SimulatedObstacle {
+    Barrier(Rect),
+    Pit(Rect),
+    Platform {
+        top: i16,
+        bounding_boxes: Vec<Rect>,
+        path: Option<PlatformPath>,
+    },
+}
+
+impl SimulatedObstacle {
+    fn right(&self) -> i16 {
+        match self {
+            SimulatedObstacle::Barrier(bounding_box) | SimulatedObstacle::Pit(bounding_box) => {
+                bounding_box.right()
+            }
+            SimulatedObstacle::Platform { bounding_boxes, .. } => bounding_boxes
+                .iter()
+                .map(Rect::right)
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub struct Checker<'a> {
+    segment_set: &'a SegmentSet,
+    sprite_sheet: &'a Sheet,
+    tile_sheet: &'a Sheet,
+    ceiling_sheet: &'a Sheet,
+    stone_size: (i16, i16),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SearchKey {
+    scrolled: i16,
+    tick: u16,
+    state: Discriminant<RedHatBoyStateMachine>,
+    frame: u8,
+    x: i16,
+    y: i16,
+    velocity_x: i16,
+    velocity_y: i16,
+}
+
+enum Outcome {
+    Continue(RedHatBoyStateMachine),
+    Cleared,
+    Failed,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(
+        segment_set: &'a SegmentSet,
+        sprite_sheet: &'a Sheet,
+        tile_sheet: &'a Sheet,
+        ceiling_sheet: &'a Sheet,
+        stone_size: (i16, i16),
+    ) -> Self {
+        Checker {
+            segment_set,
+            sprite_sheet,
+            tile_sheet,
+            ceiling_sheet,
+            stone_size,
+        }
+    }
+
+    pub fn can_clear(
+        &self,
+        segment: &SegmentDefinition,
+        running_speed: i16,
+        abilities: &Abilities,
+    ) -> bool {
+        let mut boy = RedHatBoyStateMachine::new(None);
+        boy.context_mut().set_running_speed(running_speed);
+        let boy = boy.transition(Event::Run);
+        let obstacles = self.obstacles(segment, boy.bounding_box(self.sprite_sheet).right());
+
+        // A segment is laid out well before the boy reaches it, so its moving
+        // platforms could be anywhere along their paths when he does. It has
+        // to be clearable wherever they are.
+        let cycle = cycle(&obstacles);
+        (0..cycle).all(|phase| self.search(boy.clone(), &obstacles, abilities, phase, cycle))
+    }
+
+    fn search(
+        &self,
+        boy: RedHatBoyStateMachine,
+        obstacles: &[SimulatedObstacle],
+        abilities: &Abilities,
+        phase: u16,
+        cycle: u16,
+    ) -> bool {
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![(boy, 0, phase)];
+        while let Some((boy, scrolled, tick)) = to_visit.pop() {
+            // Running straight on is tried first, since most of a segment
+            // needs nothing else and it keeps the search from wandering.
+            for event in inputs(abilities).into_iter().rev() {
+                let mut boy = match event {
+                    Some(event) => boy.clone().transition(event),
+                    None => boy.clone(),
+                };
+                boy.context_mut().over_ground = self.over_ground(&boy, obstacles, scrolled);
+                let boy = boy.update();
+                let scrolled = scrolled + boy.context().velocity.x;
+                match self.collide(boy, obstacles, scrolled, tick) {
+                    Outcome::Cleared => return true,
+                    Outcome::Failed => {}
+                    Outcome::Continue(boy) => {
+                        // Without moving platforms the cycle is a single
+                        // frame, which leaves the frame count out of the search.
+                        let tick = (tick + 1) % cycle;
+                        let key = search_key(&boy, scrolled, tick);
+                        if visited.insert(key) {
+                            to_visit.push((boy, scrolled, tick));
+                        }
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    fn obstacles(&self, segment: &SegmentDefinition, offset_x: i16) -> Vec<SimulatedObstacle> {
+        let (stone_width, stone_height) = self.stone_size;
+        let barriers = segment.barriers.iter().map(|barrier| {
+            SimulatedObstacle::Barrier(Rect::new_from_x_y(
+                offset_x + barrier.x,
+                barrier.y,
+                stone_width,
+                stone_height,
+            ))
+        });
+        let platforms = segment.platforms.iter().filter_map(|placement| {
+            self.segment_set
+                .platform(&placement.platform)
+                .map(|definition| {
+                    let position = Point {
+                        x: offset_x + placement.x,
+                        y: placement.y,
+                    };
+                    SimulatedObstacle::Platform {
+                        top: placement.y,
+                        path: placement.path,
+                        bounding_boxes: lay_out_tiles(
+                            &definition.rows(placement.length),
+                            |sprite_name| self.tile_sheet.cell(sprite_name),
+                        )
+                        .iter()
+                        .map(|(cell, offset)| tile_bounding_box(cell, position, *offset))
+                        .collect(),
+                    }
+                })
+        });
+
+        let pits = segment.pits.iter().map(|pit| {
+            SimulatedObstacle::Pit(Rect::new_from_x_y(offset_x + pit.x, GROUND, pit.width, 0))
+        });
+
+        // Overhead obstacles are as deadly as barriers wherever they are hit.
+        let ceilings = segment.ceilings.iter().map(|ceiling| {
+            let width = self
+                .ceiling_sheet
+                .cell(ceiling.kind.frame_name())
+                .map_or(0, |cell| cell.frame.w);
+            SimulatedObstacle::Barrier(Rect::new_from_x_y(
+                offset_x + ceiling.x,
+                0,
+                width,
+                ceiling.bottom,
+            ))
+        });
+
+        barriers
+            .chain(platforms)
+            .chain(pits)
+            .chain(ceilings)
+            .collect()
+    }
+
+    // Mirrors `Ground::supports`, where the ground runs everywhere but the
+    // pits.
+    fn over_ground(
+        &self,
+        boy: &RedHatBoyStateMachine,
+        obstacles: &[SimulatedObstacle],
+        scrolled: i16,
+    ) -> bool {
+        let bounding_box = boy.bounding_box(self.sprite_sheet);
+        !obstacles.iter().any(|obstacle| match obstacle {
+            SimulatedObstacle::Pit(pit) => {
+                let pit = scroll(pit, scrolled);
+                pit.x() <= bounding_box.x() && pit.right() >= bounding_box.right()
+            }
+            _ => false,
+        })
+    }
+
+    // Mirrors `Obstacle::check_intersection` and `Obstacle::update` for
+    // barriers and platforms, with any hit counting as a failure.
+    fn collide(
+        &self,
+        mut boy: RedHatBoyStateMachine,
+        obstacles: &[SimulatedObstacle],
+        scrolled: i16,
+        tick: u16,
+    ) -> Outcome {
+        if boy.context().feet() > GROUND {
+            return Outcome::Failed;
+        }
+        for obstacle in obstacles {
+            let bounding_box = boy.bounding_box(self.sprite_sheet);
+            match obstacle {
+                SimulatedObstacle::Pit(_) => {}
+                SimulatedObstacle::Barrier(barrier) => {
+                    if bounding_box.intersects(&scroll(barrier, scrolled)) {
+                        return Outcome::Failed;
+                    }
+                }
+                SimulatedObstacle::Platform {
+                    top,
+                    bounding_boxes,
+                    path,
+                } => {
+                    let offset = path.map_or(Point::default(), |path| path.offset(tick));
+                    let top = top + offset.y;
+                    if let Some(box_to_land_on) = bounding_boxes
+                        .iter()
+                        .map(|platform_box| scroll(&shift(platform_box, offset), scrolled))
+                        .find(|platform_box| bounding_box.intersects(platform_box))
+                    {
+                        let context = boy.context();
+                        if context.velocity.y > 0 && context.position.y < top {
+                            boy = boy.transition(Event::Land(box_to_land_on.y()));
+                        } else {
+                            return Outcome::Failed;
+                        }
+                    }
+                    if let Some(path) = path {
+                        if boy.standing_on(top) {
+                            let next = path.offset(tick + 1);
+                            boy.context_mut().ride(Point {
+                                x: next.x - offset.x,
+                                y: next.y - offset.y,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let boy_left = boy.bounding_box(self.sprite_sheet).x();
+        if obstacles
+            .iter()
+            .all(|obstacle| obstacle.right() - scrolled < boy_left)
+        {
+            Outcome::Cleared
+        } else {
+            Outcome::Continue(boy)
+        }
+    }
+}
+
+fn inputs(abilities: &Abilities) -> Vec<Option<Event>> {
+    let mut inputs = vec![None, Some(Event::Jump), Some(Event::Slide)];
+    if abilities.double_jump {
+        inputs.push(Some(Event::DoubleJump));
+    }
+    match abilities.air_action {
+        Some(AirAction::Dash) => inputs.push(Some(Event::AirDash)),
+        Some(AirAction::FastFall) => inputs.push(Some(Event::FastFall)),
+        None => {}
+    }
+    inputs
+}
+
+// The number of frames before every moving platform is back where it
+// started, which is 1 when nothing moves.
+fn cycle(obstacles: &[SimulatedObstacle]) -> u16 {
+    obstacles
+        .iter()
+        .filter_map(|obstacle| match obstacle {
+            SimulatedObstacle::Platform {
+                path: Some(path), ..
+            } => Some(path.period.max(1)),
+            _ => None,
+        })
+        .fold(1, |cycle, period| cycle / gcd(cycle, period) * period)
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn scroll(rect: &Rect, scrolled: i16) -> Rect {
+    Rect::new_from_x_y(rect.x() - scrolled, rect.y(), rect.width, rect.height)
+}
+
+fn shift(rect: &Rect, offset: Point) -> Rect {
+    Rect::new_from_x_y(
+        rect.x() + offset.x,
+        rect.y() + offset.y,
+        rect.width,
+        rect.height,
+    )
+}
+
+fn search_key(boy: &RedHatBoyStateMachine, scrolled: i16, tick: u16) -> SearchKey {
+    let context = boy.context();
+    SearchKey {
+        scrolled,
+        tick,
+        state: discriminant(boy),
+        frame: context.frame,
+        x: context.position.x,
+        y: context.position.y,
+        velocity_x: context.velocity.x,
+        velocity_y: context.velocity.y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::DifficultyCurve;
+    use crate::game::Ability;
+    use std::fs;
+
+    fn load<T: serde::de::DeserializeOwned>(path: &str) -> T {
+        let json = fs::read_to_string(format!("{}/static/{}", env!("CARGO_MANIFEST_DIR"), path))
+            .unwrap_or_else(|err| panic!("Could not read {} {:#?}", path, err));
+        serde_json::from_str(&json)
+            .unwrap_or_else(|err| panic!("Could not deserialize {} {:#?}", path, err))
+    }
+
+    // Width and height from the IHDR chunk, which always directly follows
+    // the PNG signature.
+    fn png_size(path: &str) -> (i16, i16) {
+        let bytes = fs::read(format!("{}/static/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap();
+        let dimension = |offset: usize| {
+            u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as i16
+        };
+        (dimension(16), dimension(20))
+    }
+
+    // The shipped sheets every check runs against.
+    struct Sheets {
+        sprite: Sheet,
+        tile: Sheet,
+        ceiling: Sheet,
+        stone_size: (i16, i16),
+    }
+
+    impl Sheets {
+        fn load() -> Self {
+            Sheets {
+                sprite: load("rhb.json"),
+                tile: load("tiles.json"),
+                ceiling: load("overhead.json"),
+                stone_size: png_size("Stone.png"),
+            }
+        }
+
+        fn checker<'a>(&'a self, segment_set: &'a SegmentSet) -> Checker<'a> {
+            Checker::new(
+                segment_set,
+                &self.sprite,
+                &self.tile,
+                &self.ceiling,
+                self.stone_size,
+            )
+        }
+    }
+
+    #[test]
+    fn every_shipped_segment_can_be_cleared_at_every_speed() {
+        let segment_set: SegmentSet = load("segments.json");
+        let curve: DifficultyCurve = load("difficulty.json");
+        let curve = DifficultyCurve::new(curve.stages().to_vec()).unwrap();
+        let sheets = Sheets::load();
+        let checker = sheets.checker(&segment_set);
+
+        let mut abilities = Abilities::default();
+        let mut failures = vec![];
+        for stage in curve.stages() {
+            stage
+                .unlocks
+                .iter()
+                .for_each(|ability| abilities.unlock(*ability));
+            segment_set
+                .iter()
+                .filter(|segment| segment.difficulty <= stage.max_segment_difficulty)
+                .filter(|segment| {
+                    segment
+                        .requires
+                        .map_or(true, |ability| abilities.has(ability))
+                })
+                .filter(|segment| !checker.can_clear(segment, stage.running_speed, &abilities))
+                .for_each(|segment| {
+                    failures.push(format!("{} at speed {}", segment.name, stage.running_speed));
+                });
+        }
+
+        assert!(
+            failures.is_empty(),
+            "Segments cannot be cleared: {:#?}",
+            failures
+        );
+    }
+
+    #[test]
+    fn stone_wall_needs_a_double_jump() {
+        let segment_set: SegmentSet = load("segments.json");
+        let sheets = Sheets::load();
+        let checker = sheets.checker(&segment_set);
+        let stone_wall = segment_set.segment("stone_wall").unwrap();
+        let mut abilities = Abilities::default();
+
+        assert!(!checker.can_clear(stone_wall, 4, &abilities));
+        abilities.unlock(Ability::DoubleJump);
+        assert!(checker.can_clear(stone_wall, 4, &abilities));
+    }
+
+    #[test]
+    fn moving_platforms_must_be_reachable_wherever_they_are_on_their_path() {
+        let segment_set: SegmentSet = serde_json::from_str(
+            r#"{
+                "start": "bridge",
+                "platforms": {
+                    "floating": {
+                        "top": { "left": "13.png", "middle": "14.png", "right": "15.png" }
+                    }
+                },
+                "segments": [
+                    {
+                        "name": "bridge",
+                        "difficulty": 1,
+                        "barriers": [
+                            { "x": 150, "y": 546 },
+                            { "x": 240, "y": 546 },
+                            { "x": 330, "y": 546 }
+                        ],
+                        "platforms": [{ "platform": "floating", "x": 100, "y": 400, "length": 3 }]
+                    },
+                    {
+                        "name": "bobbing_bridge",
+                        "difficulty": 1,
+                        "barriers": [
+                            { "x": 150, "y": 546 },
+                            { "x": 240, "y": 546 },
+                            { "x": 330, "y": 546 }
+                        ],
+                        "platforms": [
+                            {
+                                "platform": "floating",
+                                "x": 100,
+                                "y": 400,
+                                "length": 3,
+                                "path": { "axis": "Vertical", "distance": -20, "period": 60 }
+                            }
+                        ]
+                    },
+                    {
+                        "name": "elevator",
+                        "difficulty": 1,
+                        "barriers": [
+                            { "x": 150, "y": 546 },
+                            { "x": 240, "y": 546 },
+                            { "x": 330, "y": 546 }
+                        ],
+                        "platforms": [
+                            {
+                                "platform": "floating",
+                                "x": 100,
+                                "y": 400,
+                                "length": 3,
+                                "path": { "axis": "Vertical", "distance": -300, "period": 400 }
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let sheets = Sheets::load();
+        let checker = sheets.checker(&segment_set);
+        let abilities = Abilities::default();
+
+        // The wall is too tall to jump, so the boy has to cross on the
+        // platform. The elevator starts where the bridge is, but is out of
+        // reach for much of its path.
+        assert!(checker.can_clear(segment_set.segment("bridge").unwrap(), 4, &abilities));
+        assert!(checker.can_clear(
+            segment_set.segment("bobbing_bridge").unwrap(),
+            4,
+            &abilities
+        ));
+        assert!(!checker.can_clear(segment_set.segment("elevator").unwrap(), 4, &abilities));
+    }
+
+    #[test]
+    fn pits_wider_than_a_jump_are_impossible() {
+        let segment_set: SegmentSet = serde_json::from_str(
+            r#"{
+                "start": "narrow",
+                "platforms": {},
+                "segments": [
+                    { "name": "narrow", "difficulty": 1, "pits": [{ "x": 200, "width": 150 }] },
+                    { "name": "wide", "difficulty": 1, "pits": [{ "x": 200, "width": 600 }] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let sheets = Sheets::load();
+        let checker = sheets.checker(&segment_set);
+        let abilities = Abilities::default();
+
+        assert!(checker.can_clear(segment_set.segment("narrow").unwrap(), 4, &abilities));
+        assert!(!checker.can_clear(segment_set.segment("wide").unwrap(), 4, &abilities));
+    }
+
+    #[test]
+    fn ceilings_too_low_to_slide_under_are_impossible() {
+        let segment_set: SegmentSet = serde_json::from_str(
+            r#"{
+                "start": "sign",
+                "platforms": {},
+                "segments": [
+                    {
+                        "name": "sign",
+                        "difficulty": 1,
+                        "ceilings": [{ "kind": "Sign", "x": 200, "bottom": 480 }]
+                    },
+                    {
+                        "name": "low_sign",
+                        "difficulty": 1,
+                        "ceilings": [{ "kind": "Sign", "x": 200, "bottom": 520 }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let sheets = Sheets::load();
+        let checker = sheets.checker(&segment_set);
+        let abilities = Abilities::default();
+
+        assert!(checker.can_clear(segment_set.segment("sign").unwrap(), 4, &abilities));
+        assert!(!checker.can_clear(segment_set.segment("low_sign").unwrap(), 4, &abilities));
+    }
+}
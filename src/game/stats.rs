@@ -0,0 +1,95 @@
+use super::rhb::BoyEvent;
+
+// The game loop updates at a fixed rate, so time is counted in updates.
+const UPDATES_PER_SECOND: u32 = 60;
+
+// A tally of how a run went, shown on the results once it's over.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct RunStats {
+    updates: u32,
+    jumps: u32,
+    slides: u32,
+    obstacles_cleared: u32,
+    collectibles: u32,
+}
+
+impl RunStats {
+    pub fn tick(&mut self) {
+        self.updates += 1;
+    }
+
+    pub fn record(&mut self, event: BoyEvent) {
+        match event {
+            BoyEvent::Jumped | BoyEvent::DoubleJumped => self.jumps += 1,
+            BoyEvent::Slid => self.slides += 1,
+            BoyEvent::Collected(_) => self.collectibles += 1,
+            BoyEvent::AirDashed
+            | BoyEvent::Hurt
+            | BoyEvent::KnockedOut
+            | BoyEvent::LandedOnPlatform => {}
+        }
+    }
+
+    pub fn clear_obstacles(&mut self, count: usize) {
+        self.obstacles_cleared += count as u32;
+    }
+
+    // One line per stat, with the score compared against the best there was
+    // before this run.
+    pub fn results(&self, distance: i32, score: u32, previous_best: Option<u32>) -> Vec<String> {
+        let seconds = self.updates / UPDATES_PER_SECOND;
+        let best = match previous_best {
+            Some(best) if score > best => format!("New best by {}", score - best),
+            Some(best) => format!("{} short of your best", best - score),
+            None => "First score on the board".to_string(),
+        };
+        vec![
+            format!("Distance: {}", distance.max(0)),
+            format!("Score: {}", score),
+            best,
+            format!("Time: {}:{:02}", seconds / 60, seconds % 60),
+            format!("Jumps: {}", self.jumps),
+            format!("Slides: {}", self.slides),
+            format!("Obstacles cleared: {}", self.obstacles_cleared),
+            format!("Collectibles: {}", self.collectibles),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_tally_the_run_against_the_previous_best() {
+        let mut stats = RunStats::default();
+        (0..UPDATES_PER_SECOND * 75).for_each(|_| stats.tick());
+        [
+            BoyEvent::Jumped,
+            BoyEvent::DoubleJumped,
+            BoyEvent::Slid,
+            BoyEvent::Hurt,
+            BoyEvent::Collected(10),
+        ]
+        .into_iter()
+        .for_each(|event| stats.record(event));
+        stats.clear_obstacles(3);
+
+        let results = stats.results(1234, 22, Some(30));
+        assert_eq!(
+            results,
+            [
+                "Distance: 1234",
+                "Score: 22",
+                "8 short of your best",
+                "Time: 1:15",
+                "Jumps: 2",
+                "Slides: 1",
+                "Obstacles cleared: 3",
+                "Collectibles: 1",
+            ]
+        );
+        assert_eq!(stats.results(0, 40, Some(30))[2], "New best by 10");
+        assert_eq!(stats.results(0, 40, None)[2], "First score on the board");
+    }
+}
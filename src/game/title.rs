@@ -0,0 +1,411 @@
+use super::{
+    editing::Editing, practice::Practice, race::Race, tutorial::Tutorial, Ready, Walk,
+    WalkTheDogState, WalkTheDogStateMachine, HEIGHT,
+};
+use crate::{
+    browser,
+    engine::{self, KeyState, MouseDownListener, Point, Rect, Renderer},
+    settings::SETTINGS,
+};
+
+const WIDTH: i16 = 600;
+const TITLE: &str = "Walk the Dog";
+const TITLE_POSITION: Point = Point { x: 300, y: 150 };
+const TITLE_FONT: &str = "36pt 'Ken Future'";
+const ENTRY_FONT: &str = "20pt 'Ken Future'";
+const TEXT_FONT: &str = "14pt 'Ken Future'";
+const TEXT_COLOR: &str = "white";
+const SELECTED_COLOR: &str = "gold";
+const SHADE_COLOR: &str = "rgba(0, 0, 0, 0.45)";
+const FIRST_ENTRY_Y: i16 = 240;
+const ENTRY_HEIGHT: i16 = 34;
+const ENTRY_WIDTH: i16 = 240;
+const LINE_HEIGHT: i16 = 32;
+const DAILY_DAYS_SHOWN: usize = 3;
+const BACK_HINT: &str = "Esc or click: back";
+const SETTINGS_HINT: &str = "Arrows: change  Enter: rebind  Esc: back";
+const REBIND_HINT: &str = "Press a key  Esc: cancel";
+const FIRST_SETTING_Y: i16 = 215;
+const SETTING_HEIGHT: i16 = 36;
+const BACK_HINT_Y: i16 = 520;
+// The boy hops every so often while the attract mode runs behind the menu.
+const ATTRACT_JUMP_INTERVAL: u32 = 150;
+const CREDITS: [&str; 4] = [
+    "Red Hat Boy and tiles by GameArt2D",
+    "Ken Future font by Kenney",
+    "Based on Game Development with",
+    "Rust and WebAssembly",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Endless,
+    Daily,
+    Race,
+    Tutorial,
+    Practice,
+    Editor,
+    Settings,
+    HighScores,
+    Achievements,
+    Credits,
+}
+
+const ENTRIES: [Entry; 10] = [
+    Entry::Endless,
+    Entry::Daily,
+    Entry::Race,
+    Entry::Tutorial,
+    Entry::Practice,
+    Entry::Editor,
+    Entry::Settings,
+    Entry::HighScores,
+    Entry::Achievements,
+    Entry::Credits,
+];
+
+impl Entry {
+    fn label(&self) -> &str {
+        match self {
+            Entry::Endless => "Endless",
+            Entry::Daily => "Daily Challenge",
+            Entry::Race => "Two Players",
+            Entry::Tutorial => "Tutorial",
+            Entry::Practice => "Practice",
+            Entry::Editor => "Editor",
+            Entry::Settings => "Settings",
+            Entry::HighScores => "High Scores",
+            Entry::Achievements => "Achievements",
+            Entry::Credits => "Credits",
+        }
+    }
+}
+
+pub struct Title {
+    clicks: MouseDownListener,
+    selected: usize,
+    // The entry whose page is open, if any, shown in place of the menu.
+    page: Option<Entry>,
+    frame: u32,
+    setting: usize,
+    rebinding: bool,
+}
+
+pub enum TitleEndState {
+    Continue(WalkTheDogState<Title>),
+    Play(WalkTheDogState<Ready>),
+    Race(WalkTheDogState<Race>),
+    Tutorial(WalkTheDogState<Tutorial>),
+    Practice(WalkTheDogState<Practice>),
+    Edit(WalkTheDogState<Editing>),
+}
+
+impl From<TitleEndState> for WalkTheDogStateMachine {
+    fn from(state: TitleEndState) -> Self {
+        match state {
+            TitleEndState::Continue(title) => title.into(),
+            TitleEndState::Play(ready) => ready.into(),
+            TitleEndState::Race(race) => race.into(),
+            TitleEndState::Tutorial(tutorial) => tutorial.into(),
+            TitleEndState::Practice(practice) => practice.into(),
+            TitleEndState::Edit(editing) => editing.into(),
+        }
+    }
+}
+
+impl WalkTheDogState<Title> {
+    pub(super) fn new(walk: Walk) -> Self {
+        let clicks = browser::canvas()
+            .map(|canvas| engine::add_mouse_down_handler(canvas.into()))
+            .unwrap();
+        WalkTheDogState {
+            _state: Title {
+                clicks,
+                selected: 0,
+                page: None,
+                frame: 0,
+                setting: 0,
+                rebinding: false,
+            },
+            walk: Box::new(Walk::attract(walk)),
+        }
+    }
+
+    pub(super) fn update(mut self, keystate: &KeyState) -> TitleEndState {
+        self._state.frame = self._state.frame.wrapping_add(1);
+        self.walk
+            .run_attract_mode(self._state.frame % ATTRACT_JUMP_INTERVAL == 0);
+
+        let mut chosen = None;
+        match self._state.page {
+            Some(Entry::Settings) => {
+                if self.update_settings(keystate) {
+                    return TitleEndState::Continue(self.close_settings());
+                }
+            }
+            Some(_) => {
+                let clicked = self._state.clicks.next_click().is_some();
+                if clicked || keystate.is_just_pressed("Escape") {
+                    self._state.page = None;
+                }
+            }
+            None => {
+                if keystate.is_just_pressed("ArrowUp") {
+                    self._state.selected =
+                        (self._state.selected + ENTRIES.len() - 1) % ENTRIES.len();
+                }
+                if keystate.is_just_pressed("ArrowDown") {
+                    self._state.selected = (self._state.selected + 1) % ENTRIES.len();
+                }
+                if keystate.is_just_pressed("Enter") || keystate.is_just_pressed("Space") {
+                    chosen = Some(ENTRIES[self._state.selected]);
+                }
+                while let Some(position) = self._state.clicks.next_click() {
+                    if let Some(index) = entry_at(position) {
+                        self._state.selected = index;
+                        chosen = Some(ENTRIES[index]);
+                    }
+                }
+            }
+        }
+
+        match chosen {
+            Some(Entry::Endless) => TitleEndState::Play(self.play(Walk::reset)),
+            Some(Entry::Daily) => {
+                let date = browser::utc_date();
+                TitleEndState::Play(self.play(|walk| Walk::daily(walk, date)))
+            }
+            Some(Entry::Race) => {
+                self.walk.start_music();
+                TitleEndState::Race(WalkTheDogState::<Race>::new(*self.walk))
+            }
+            Some(Entry::Tutorial) => {
+                self.walk.start_music();
+                TitleEndState::Tutorial(WalkTheDogState::<Tutorial>::new(*self.walk))
+            }
+            Some(Entry::Practice) => {
+                self.walk.start_music();
+                TitleEndState::Practice(WalkTheDogState::<Practice>::new(*self.walk))
+            }
+            Some(Entry::Editor) => TitleEndState::Edit(self.play(Walk::reset).edit()),
+            Some(entry) => {
+                self._state.page = Some(entry);
+                TitleEndState::Continue(self)
+            }
+            None => TitleEndState::Continue(self),
+        }
+    }
+
+    pub(super) fn draw_title(&self, renderer: &Renderer) {
+        self.walk.draw_scene(renderer);
+        renderer.fill_rect(&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT), SHADE_COLOR);
+        draw_text(renderer, TITLE, TITLE_POSITION, TITLE_FONT, TEXT_COLOR);
+        match self._state.page {
+            None => self.draw_menu(renderer),
+            Some(Entry::HighScores) => self.draw_high_scores(renderer),
+            Some(Entry::Achievements) => draw_lines(renderer, &self.walk.achievements.summaries()),
+            Some(Entry::Credits) => draw_lines(renderer, &CREDITS),
+            Some(Entry::Settings) => self.draw_settings(renderer),
+            Some(
+                Entry::Endless
+                | Entry::Daily
+                | Entry::Race
+                | Entry::Tutorial
+                | Entry::Practice
+                | Entry::Editor,
+            ) => {}
+        }
+    }
+
+    // Returns true once the player is done with the settings.
+    fn update_settings(&mut self, keystate: &KeyState) -> bool {
+        let setting = SETTINGS[self._state.setting];
+        if self._state.rebinding {
+            if let Some(code) = keystate.just_pressed().next() {
+                if code != "Escape" {
+                    self.walk.settings.bind(setting, code);
+                }
+                self._state.rebinding = false;
+            }
+            return false;
+        }
+
+        if keystate.is_just_pressed("Escape") {
+            return true;
+        }
+        if keystate.is_just_pressed("ArrowUp") {
+            self._state.setting = (self._state.setting + SETTINGS.len() - 1) % SETTINGS.len();
+        }
+        if keystate.is_just_pressed("ArrowDown") {
+            self._state.setting = (self._state.setting + 1) % SETTINGS.len();
+        }
+        if keystate.is_just_pressed("ArrowLeft") {
+            self.walk.settings.adjust(setting, -1);
+        }
+        if keystate.is_just_pressed("ArrowRight") {
+            self.walk.settings.adjust(setting, 1);
+        }
+        if keystate.is_just_pressed("Enter") || keystate.is_just_pressed("Space") {
+            self.choose_setting();
+        }
+        while let Some(position) = self._state.clicks.next_click() {
+            match setting_at(position) {
+                Some(index) => {
+                    self._state.setting = index;
+                    self.choose_setting();
+                }
+                None => return true,
+            }
+        }
+        // Volumes are heard straight away so they can be judged by ear.
+        self.walk.apply_settings();
+        false
+    }
+
+    fn choose_setting(&mut self) {
+        let setting = SETTINGS[self._state.setting];
+        if setting.is_key() {
+            self._state.rebinding = true;
+        } else {
+            self.walk.settings.adjust(setting, 1);
+        }
+    }
+
+    // Saves the settings and restarts the scene behind the menu, since
+    // reduced motion changes whether it runs.
+    fn close_settings(mut self) -> Self {
+        self.walk.settings.save();
+        self.walk.apply_settings();
+        self._state.page = None;
+        WalkTheDogState {
+            _state: self._state,
+            walk: Box::new(Walk::attract(*self.walk)),
+        }
+    }
+
+    fn draw_settings(&self, renderer: &Renderer) {
+        SETTINGS.iter().enumerate().for_each(|(index, setting)| {
+            let selected = index == self._state.setting;
+            let label = if selected && self._state.rebinding {
+                format!("{}...", self.walk.settings.describe(*setting))
+            } else {
+                self.walk.settings.describe(*setting)
+            };
+            let color = if selected { SELECTED_COLOR } else { TEXT_COLOR };
+            draw_text(renderer, &label, setting_position(index), TEXT_FONT, color);
+        });
+        let hint = if self._state.rebinding {
+            REBIND_HINT
+        } else {
+            SETTINGS_HINT
+        };
+        let position = Point {
+            x: WIDTH / 2,
+            y: BACK_HINT_Y,
+        };
+        draw_text(renderer, hint, position, TEXT_FONT, TEXT_COLOR);
+    }
+
+    fn draw_menu(&self, renderer: &Renderer) {
+        ENTRIES.iter().enumerate().for_each(|(index, entry)| {
+            let (label, color) = if index == self._state.selected {
+                (format!("> {} <", entry.label()), SELECTED_COLOR)
+            } else {
+                (entry.label().to_string(), TEXT_COLOR)
+            };
+            draw_text(renderer, &label, entry_position(index), ENTRY_FONT, color);
+        });
+    }
+
+    // The endless table, followed by the last few daily challenges.
+    fn draw_high_scores(&self, renderer: &Renderer) {
+        let scores = self.walk.high_scores.scores();
+        let daily = self
+            .walk
+            .daily_scores
+            .recent()
+            .take(DAILY_DAYS_SHOWN)
+            .map(|(date, score)| format!("Daily {}: {}", date, score));
+        let lines: Vec<String> = scores
+            .iter()
+            .enumerate()
+            .map(|(place, score)| format!("{}. {}", place + 1, score))
+            .chain(daily)
+            .collect();
+        if lines.is_empty() {
+            draw_lines(renderer, &["No scores yet"]);
+        } else {
+            draw_lines(renderer, &lines);
+        }
+    }
+
+    fn play(mut self, start: impl FnOnce(Walk) -> Walk) -> WalkTheDogState<Ready> {
+        self.walk.start_music();
+        WalkTheDogState::<Ready>::new(start(*self.walk))
+    }
+}
+
+fn entry_position(index: usize) -> Point {
+    Point {
+        x: WIDTH / 2,
+        y: FIRST_ENTRY_Y + index as i16 * ENTRY_HEIGHT,
+    }
+}
+
+// Entries are drawn on their baseline, so each one's clickable area sits
+// mostly above its position.
+fn entry_at(position: Point) -> Option<usize> {
+    let target = Rect::new(position, 1, 1);
+    (0..ENTRIES.len()).find(|&index| {
+        let entry = entry_position(index);
+        Rect::new_from_x_y(
+            entry.x - ENTRY_WIDTH / 2,
+            entry.y - ENTRY_HEIGHT + 10,
+            ENTRY_WIDTH,
+            ENTRY_HEIGHT,
+        )
+        .intersects(&target)
+    })
+}
+
+fn setting_position(index: usize) -> Point {
+    Point {
+        x: WIDTH / 2,
+        y: FIRST_SETTING_Y + index as i16 * SETTING_HEIGHT,
+    }
+}
+
+fn setting_at(position: Point) -> Option<usize> {
+    let target = Rect::new(position, 1, 1);
+    (0..SETTINGS.len()).find(|&index| {
+        let setting = setting_position(index);
+        Rect::new_from_x_y(
+            setting.x - ENTRY_WIDTH,
+            setting.y - SETTING_HEIGHT + 8,
+            ENTRY_WIDTH * 2,
+            SETTING_HEIGHT,
+        )
+        .intersects(&target)
+    })
+}
+
+fn draw_lines(renderer: &Renderer, lines: &[impl AsRef<str>]) {
+    lines.iter().enumerate().for_each(|(index, line)| {
+        let position = Point {
+            x: WIDTH / 2,
+            y: FIRST_ENTRY_Y + index as i16 * LINE_HEIGHT,
+        };
+        draw_text(renderer, line.as_ref(), position, TEXT_FONT, TEXT_COLOR);
+    });
+    let hint = Point {
+        x: WIDTH / 2,
+        y: BACK_HINT_Y,
+    };
+    draw_text(renderer, BACK_HINT, hint, TEXT_FONT, TEXT_COLOR);
+}
+
+fn draw_text(renderer: &Renderer, text: &str, position: Point, font: &str, color: &str) {
+    if let Err(err) = renderer.draw_centered_text(text, &position, font, color) {
+        error!("Could not draw title screen text {:#?}", err);
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+
+use crate::engine::{Point, Rect, Renderer};
+
+const TOAST_FRAMES: u16 = 180;
+const TOAST_POSITION: Point = Point { x: 300, y: 130 };
+const TOAST_WIDTH: i16 = 440;
+const TOAST_HEIGHT: i16 = 40;
+const TOAST_FONT: &str = "16pt 'Ken Future'";
+const TOAST_COLOR: &str = "gold";
+const SHADE_COLOR: &str = "rgba(0, 0, 0, 0.6)";
+
+// Short messages shown one after another across the top of the screen.
+#[derive(Default)]
+pub struct Toasts {
+    queue: VecDeque<String>,
+    shown_for: u16,
+}
+
+impl Toasts {
+    pub fn push(&mut self, message: String) {
+        self.queue.push_back(message);
+    }
+
+    pub fn update(&mut self) {
+        if self.queue.is_empty() {
+            return;
+        }
+        self.shown_for += 1;
+        if self.shown_for >= TOAST_FRAMES {
+            self.queue.pop_front();
+            self.shown_for = 0;
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer) {
+        if let Some(message) = self.queue.front() {
+            renderer.fill_rect(
+                &Rect::new_from_x_y(
+                    TOAST_POSITION.x - TOAST_WIDTH / 2,
+                    TOAST_POSITION.y - TOAST_HEIGHT + 10,
+                    TOAST_WIDTH,
+                    TOAST_HEIGHT,
+                ),
+                SHADE_COLOR,
+            );
+            if let Err(err) =
+                renderer.draw_centered_text(message, &TOAST_POSITION, TOAST_FONT, TOAST_COLOR)
+            {
+                error!("Could not draw toast {:#?}", err);
+            }
+        }
+    }
+}
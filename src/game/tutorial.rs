@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+
+use super::{
+    title::Title, Course, Ready, Walk, WalkTheDogState, WalkTheDogStateMachine, TIMELINE_MINIMUM,
+};
+use crate::{
+    browser::ui::{Panel, UiEvent},
+    engine::{KeyState, Point, Rect, Renderer},
+    segments::SegmentDefinition,
+    settings::{key_name, KeyBindings},
+};
+
+const PROMPT_POSITION: Point = Point { x: 300, y: 220 };
+const PROMPT_WIDTH: i16 = 500;
+const PROMPT_HEIGHT: i16 = 50;
+const PROMPT_FONT: &str = "18pt 'Ken Future'";
+const PROMPT_COLOR: &str = "white";
+const SHADE_COLOR: &str = "rgba(0, 0, 0, 0.6)";
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Action {
+    Run,
+    Jump,
+    Slide,
+}
+
+impl Action {
+    fn key<'a>(&self, keys: &'a KeyBindings) -> &'a str {
+        match self {
+            Action::Run => &keys.run,
+            Action::Jump => &keys.jump,
+            Action::Slide => &keys.slide,
+        }
+    }
+}
+
+// Stops the walk once the point `at` along its segment reaches the front of
+// the boy, until the player does `action`. The text names keys as {run},
+// {jump} and {slide}, which are filled in from the player's settings.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Prompt {
+    at: i16,
+    action: Action,
+    text: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ScriptedSegment {
+    segment: SegmentDefinition,
+    #[serde(default)]
+    prompts: Vec<Prompt>,
+}
+
+// The tutorial course, laid out one segment after another in order.
+#[derive(Deserialize, Default, Clone)]
+pub struct TutorialScript {
+    segments: Vec<ScriptedSegment>,
+}
+
+impl TryFrom<wasm_bindgen::JsValue> for TutorialScript {
+    type Error = Error;
+
+    fn try_from(json: wasm_bindgen::JsValue) -> Result<Self> {
+        serde_wasm_bindgen::from_value(json)
+            .map_err(|err| anyhow!("Could not deserialize json into a tutorial {:#?}", err))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TutorialAction {
+    Play,
+    Again,
+    Menu,
+}
+
+pub struct Tutorial {
+    upcoming: VecDeque<ScriptedSegment>,
+    // Prompts on the segments laid out so far, each with the distance at
+    // which it stops the walk.
+    prompts: VecDeque<(i32, Prompt)>,
+    // The distance at which the boy is past the last segment laid out.
+    course_end: i32,
+    panel: Option<Panel<TutorialAction>>,
+}
+
+pub enum TutorialEndState {
+    Continue(WalkTheDogState<Tutorial>),
+    Play(WalkTheDogState<Ready>),
+    Menu(WalkTheDogState<Title>),
+}
+
+impl From<TutorialEndState> for WalkTheDogStateMachine {
+    fn from(state: TutorialEndState) -> Self {
+        match state {
+            TutorialEndState::Continue(tutorial) => tutorial.into(),
+            TutorialEndState::Play(ready) => ready.into(),
+            TutorialEndState::Menu(title) => title.into(),
+        }
+    }
+}
+
+impl WalkTheDogState<Tutorial> {
+    pub(super) fn new(walk: Walk) -> Self {
+        let mut upcoming: VecDeque<ScriptedSegment> =
+            walk.tutorial.segments.iter().cloned().collect();
+        let first = upcoming.pop_front().unwrap_or_else(|| ScriptedSegment {
+            segment: walk.segments.starting_segment().clone(),
+            prompts: vec![],
+        });
+        let walk = Walk {
+            course: Course::Scripted,
+            ..Walk::reset_with_segment(walk, &first.segment)
+        };
+        let mut state = WalkTheDogState {
+            _state: Tutorial {
+                upcoming,
+                prompts: VecDeque::new(),
+                course_end: 0,
+                panel: None,
+            },
+            walk: Box::new(walk),
+        };
+        state.laid_out(first, 0);
+        state.lay_out();
+        state
+    }
+
+    pub(super) fn update(mut self, keystate: &KeyState) -> TutorialEndState {
+        match self
+            ._state
+            .panel
+            .as_mut()
+            .and_then(|panel| panel.next_event())
+        {
+            Some(UiEvent::Clicked(TutorialAction::Play)) => {
+                return TutorialEndState::Play(WalkTheDogState::<Ready>::new(Walk::reset(
+                    *self.walk,
+                )));
+            }
+            Some(UiEvent::Clicked(TutorialAction::Again)) => {
+                return TutorialEndState::Continue(WalkTheDogState::<Tutorial>::new(*self.walk));
+            }
+            Some(UiEvent::Clicked(TutorialAction::Menu)) => {
+                return TutorialEndState::Menu(WalkTheDogState::<Title>::new(*self.walk));
+            }
+            _ => {}
+        }
+
+        let keys = self.walk.settings.keys.clone();
+        if let Some(prompt) = self.waiting() {
+            if !keystate.is_just_pressed(prompt.action.key(&keys)) {
+                return TutorialEndState::Continue(self);
+            }
+            if prompt.action == Action::Run {
+                self.walk.boy.run_right();
+                self.walk.dog.run_right();
+            }
+            self._state.prompts.pop_front();
+        }
+
+        self.walk.update(keystate, &keys);
+        self.lay_out();
+        if self.walk.knocked_out() || self.walk.fell_into_pit() {
+            return TutorialEndState::Continue(WalkTheDogState::<Tutorial>::new(*self.walk));
+        }
+        if self.finished() && self._state.panel.is_none() {
+            self._state.panel = Some(finished_panel());
+        }
+        TutorialEndState::Continue(self)
+    }
+
+    pub(super) fn draw_tutorial(&self, renderer: &Renderer) {
+        self.walk.draw(renderer);
+        self.walk.run.toasts.draw(renderer);
+        if let Some(prompt) = self.waiting() {
+            renderer.fill_rect(
+                &Rect::new_from_x_y(
+                    PROMPT_POSITION.x - PROMPT_WIDTH / 2,
+                    PROMPT_POSITION.y - PROMPT_HEIGHT + 14,
+                    PROMPT_WIDTH,
+                    PROMPT_HEIGHT,
+                ),
+                SHADE_COLOR,
+            );
+            let text = prompt_text(&prompt.text, &self.walk.settings.keys);
+            if let Err(err) =
+                renderer.draw_centered_text(&text, &PROMPT_POSITION, PROMPT_FONT, PROMPT_COLOR)
+            {
+                error!("Could not draw tutorial prompt {:#?}", err);
+            }
+        }
+    }
+
+    // The prompt the walk is stopped at, if it has reached one.
+    fn waiting(&self) -> Option<&Prompt> {
+        let front = self.walk.distance + i32::from(self.walk.boy.bounding_box().right());
+        self._state
+            .prompts
+            .front()
+            .filter(|(at, _prompt)| front >= *at)
+            .map(|(_at, prompt)| prompt)
+    }
+
+    fn finished(&self) -> bool {
+        let back = self.walk.distance + i32::from(self.walk.boy.bounding_box().x());
+        self._state.upcoming.is_empty()
+            && self._state.prompts.is_empty()
+            && back > self._state.course_end
+    }
+
+    // Keeps the course laid out ahead of the boy, as the walk would for
+    // random segments.
+    fn lay_out(&mut self) {
+        while self.walk.timeline < TIMELINE_MINIMUM {
+            let Some(next) = self._state.upcoming.pop_front() else {
+                break;
+            };
+            let offset_x = self.walk.place_segment(&next.segment);
+            self.laid_out(next, self.walk.distance + i32::from(offset_x));
+        }
+    }
+
+    fn laid_out(&mut self, segment: ScriptedSegment, origin: i32) {
+        self._state.prompts.extend(
+            segment
+                .prompts
+                .into_iter()
+                .map(|prompt| (origin + i32::from(prompt.at), prompt)),
+        );
+        self._state.course_end = self.walk.distance + i32::from(self.walk.timeline);
+    }
+}
+
+fn finished_panel() -> Panel<TutorialAction> {
+    Panel::new()
+        .and_then(|mut panel| {
+            panel.label("Tutorial complete!")?;
+            panel.button(TutorialAction::Play, "Play")?;
+            panel.button(TutorialAction::Again, "Try again")?;
+            panel.button(TutorialAction::Menu, "Menu")?;
+            Ok(panel)
+        })
+        .unwrap()
+}
+
+fn prompt_text(text: &str, keys: &KeyBindings) -> String {
+    text.replace("{run}", key_name(&keys.run))
+        .replace("{jump}", key_name(&keys.jump))
+        .replace("{slide}", key_name(&keys.slide))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_tutorial_prompts_in_order_with_the_players_keys() {
+        let script: TutorialScript =
+            serde_json::from_str(include_str!("../../static/tutorial.json")).unwrap();
+        let prompts: Vec<&Prompt> = script
+            .segments
+            .iter()
+            .flat_map(|segment| &segment.prompts)
+            .collect();
+        assert_eq!(prompts[0].action, Action::Run);
+        script.segments.iter().for_each(|segment| {
+            assert!(segment
+                .prompts
+                .windows(2)
+                .all(|pair| pair[0].at <= pair[1].at));
+        });
+
+        let keys = KeyBindings {
+            jump: "KeyW".into(),
+            ..KeyBindings::default()
+        };
+        assert_eq!(
+            prompt_text("Press {jump} then {slide}", &keys),
+            "Press W then ArrowDown"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::browser;
+
+const STORAGE_KEY: &str = "walk_the_dog.high_scores";
+const MAX_HIGH_SCORES: usize = 5;
+
+// The best scores so far, highest first, kept in the browser's local storage
+// between visits.
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct HighScores {
+    scores: Vec<u32>,
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        browser::load(STORAGE_KEY)
+            .unwrap_or_else(|err| {
+                error!("Could not load high scores {:#?}", err);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = browser::store(STORAGE_KEY, self) {
+            error!("Could not save high scores {:#?}", err);
+        }
+    }
+
+    pub fn scores(&self) -> &[u32] {
+        &self.scores
+    }
+
+    // Returns the score's place in the table, or None if it wasn't good
+    // enough to make it in.
+    pub fn record(&mut self, score: u32) -> Option<usize> {
+        let place = self
+            .scores
+            .iter()
+            .take_while(|&&best| best >= score)
+            .count();
+        if place >= MAX_HIGH_SCORES || score == 0 {
+            return None;
+        }
+        self.scores.insert(place, score);
+        self.scores.truncate(MAX_HIGH_SCORES);
+        Some(place)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_best_scores_are_kept_in_order() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.record(0), None);
+        [30, 50, 10, 40, 20]
+            .iter()
+            .for_each(|&score| assert!(high_scores.record(score).is_some()));
+        assert_eq!(high_scores.scores(), &[50, 40, 30, 20, 10]);
+
+        assert_eq!(high_scores.record(5), None);
+        assert_eq!(high_scores.record(45), Some(1));
+        assert_eq!(high_scores.record(40), Some(3));
+        assert_eq!(high_scores.scores(), &[50, 45, 40, 40, 30]);
+    }
+}
@@ -4,10 +4,17 @@ use wasm_bindgen::prelude::*;
 
 #[macro_use]
 mod browser;
+mod achievements;
+mod daily;
+mod difficulty;
+mod editor;
 mod engine;
 mod game;
+mod high_scores;
 mod segments;
+mod settings;
 mod sound;
+mod tiled;
 
 // This is like the `main` function, except for JavaScript.
 #[wasm_bindgen(start)]
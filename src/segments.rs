@@ -1,92 +1,600 @@
+use std::collections::HashMap;
 use std::rc::Rc;
+
+use anyhow::{anyhow, Error, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 use web_sys::HtmlImageElement;
 
-use crate::engine::{Image, Point, Rect, SpriteSheet};
-use crate::game::{Barrier, Obstacle, Platform};
-
-const LOW_PLATFORM: i16 = 420;
-const HIGH_PLATFORM: i16 = 375;
-const FIRST_PLATFORM: i16 = 370;
-
-const STONE_ON_GROUND: i16 = 546;
-
-const FLOATING_PLATFORM_SPRITES: [&str; 3] = ["13.png", "14.png", "15.png"];
-const PLATFORM_WIDTH: i16 = 384;
-const PLATFORM_HEIGHT: i16 = 93;
-const PLATFORM_EDGE_WIDTH: i16 = 60;
-const PLATFORM_EDGE_HEIGHT: i16 = 54;
-const FLOATING_PLATFORM_BOUNDING_BOXES: [Rect; 3] = [
-    Rect::new_from_x_y(0, 0, PLATFORM_EDGE_WIDTH, PLATFORM_EDGE_HEIGHT),
-    Rect::new_from_x_y(
-        PLATFORM_EDGE_WIDTH,
-        0,
-        PLATFORM_WIDTH - (PLATFORM_EDGE_WIDTH * 2),
-        PLATFORM_HEIGHT,
-    ),
-    Rect::new_from_x_y(
-        PLATFORM_WIDTH - PLATFORM_EDGE_WIDTH,
-        0,
-        PLATFORM_EDGE_WIDTH,
-        PLATFORM_EDGE_HEIGHT,
-    ),
-];
-
-fn create_floating_platform(sprite_sheet: Rc<SpriteSheet>, position: Point) -> Platform {
-    Platform::new(
-        sprite_sheet,
-        position,
-        &FLOATING_PLATFORM_SPRITES,
-        &FLOATING_PLATFORM_BOUNDING_BOXES,
-    )
-}
-
-pub fn stone_and_platform(
-    stone: HtmlImageElement,
-    sprite_sheet: Rc<SpriteSheet>,
-    offset_x: i16,
-) -> Vec<Box<dyn Obstacle>> {
-    const INITIAL_STONE_OFFSET: i16 = 150;
-
-    vec![
-        Box::new(Barrier::new(Image::new(
-            stone,
-            Point {
-                x: offset_x + INITIAL_STONE_OFFSET,
-                y: STONE_ON_GROUND,
-            },
-        ))),
-        Box::new(create_floating_platform(
-            sprite_sheet,
-            Point {
-                x: offset_x + FIRST_PLATFORM,
-                y: LOW_PLATFORM,
-            },
-        )),
-    ]
-}
-
-pub fn platform_and_stone(
-    stone: HtmlImageElement,
-    sprite_sheet: Rc<SpriteSheet>,
-    offset_x: i16,
-) -> Vec<Box<dyn Obstacle>> {
-    const INITIAL_STONE_OFFSET: i16 = 400;
-    const INITIAL_PLATFORM_OFFSET: i16 = 200;
-
-    vec![
-        Box::new(Barrier::new(Image::new(
-            stone,
-            Point {
-                x: offset_x + INITIAL_STONE_OFFSET,
-                y: STONE_ON_GROUND,
-            },
-        ))),
-        Box::new(create_floating_platform(
-            sprite_sheet,
+use crate::engine::{Image, Point, SpriteSheet};
+use crate::game::{
+    jump_offset, Abilities, Ability, Barrier, Ceiling, Collectible, CollectibleAssets,
+    MovingPlatform, Obstacle, Platform,
+};
+
+const MAX_REPEATS: u8 = 2;
+
+#[derive(Deserialize, Clone)]
+pub struct PlatformRow {
+    pub left: String,
+    pub middle: String,
+    pub right: String,
+}
+
+impl PlatformRow {
+    fn sprites(&self, length: u8) -> Vec<&str> {
+        std::iter::once(self.left.as_str())
+            .chain((0..length).map(|_| self.middle.as_str()))
+            .chain(std::iter::once(self.right.as_str()))
+            .collect()
+    }
+}
+
+fn default_length() -> u8 {
+    1
+}
+
+fn default_depth() -> u8 {
+    1
+}
+
+// A platform is a top row of a left cap, `length` middle pieces and a right
+// cap. Ground blocks are `depth` rows deep, with every row under the top
+// one drawn from `fill`.
+#[derive(Deserialize, Clone)]
+pub struct PlatformDefinition {
+    pub top: PlatformRow,
+    #[serde(default)]
+    pub fill: Option<PlatformRow>,
+    #[serde(default = "default_length")]
+    pub length: u8,
+    #[serde(default = "default_depth")]
+    pub depth: u8,
+}
+
+impl PlatformDefinition {
+    pub fn rows(&self, length: Option<u8>) -> Vec<Vec<&str>> {
+        let length = length.unwrap_or(self.length);
+        let fill = self.fill.as_ref().unwrap_or(&self.top);
+        std::iter::once(&self.top)
+            .chain((1..self.depth).map(|_| fill))
+            .map(|row| row.sprites(length))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+// Moves a platform `distance` pixels along `axis` and back again every
+// `period` frames, easing in and out at each end.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct PlatformPath {
+    pub axis: Axis,
+    pub distance: i16,
+    pub period: u16,
+}
+
+impl PlatformPath {
+    pub fn offset(&self, frame: u16) -> Point {
+        let turn = f32::from(frame % self.period.max(1)) / f32::from(self.period.max(1));
+        let eased = (1.0 - (turn * std::f32::consts::TAU).cos()) / 2.0;
+        let distance = (eased * f32::from(self.distance)).round() as i16;
+        match self.axis {
+            Axis::Horizontal => Point { x: distance, y: 0 },
+            Axis::Vertical => Point { x: 0, y: distance },
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PlatformPlacement {
+    pub platform: String,
+    pub x: i16,
+    pub y: i16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PlatformPath>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CollectibleKind {
+    Coin,
+    Bone,
+}
+
+impl CollectibleKind {
+    pub fn frame_name(&self) -> &str {
+        match self {
+            CollectibleKind::Coin => "Coin",
+            CollectibleKind::Bone => "Bone",
+        }
+    }
+
+    pub fn points(&self) -> u32 {
+        match self {
+            CollectibleKind::Coin => 1,
+            CollectibleKind::Bone => 5,
+        }
+    }
+}
+
+// A row of `count` collectibles starting at `x`, `y` and following the curve
+// of a jump, with `spacing` frames of the jump between each one.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CollectibleArc {
+    pub kind: CollectibleKind,
+    pub x: i16,
+    pub y: i16,
+    pub count: u8,
+    pub spacing: u8,
+}
+
+impl CollectibleArc {
+    pub fn positions(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..i16::from(self.count)).map(|index| {
+            let offset = jump_offset(index * i16::from(self.spacing));
             Point {
-                x: offset_x + INITIAL_PLATFORM_OFFSET,
-                y: HIGH_PLATFORM,
-            },
-        )),
-    ]
+                x: self.x + offset.x,
+                y: self.y + offset.y,
+            }
+        })
+    }
+}
+
+// A gap in the ground that has to be jumped or crossed on a platform.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct Pit {
+    pub x: i16,
+    pub width: i16,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CeilingKind {
+    Branch,
+    Sign,
+}
+
+impl CeilingKind {
+    pub fn frame_name(&self) -> &str {
+        match self {
+            CeilingKind::Branch => "Branch.png",
+            CeilingKind::Sign => "Sign.png",
+        }
+    }
+}
+
+// Something hanging down from above the screen with its lowest edge at
+// `bottom`, which has to be slid under.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct CeilingPlacement {
+    pub kind: CeilingKind,
+    pub x: i16,
+    pub bottom: i16,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentDefinition {
+    pub name: String,
+    pub difficulty: u8,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Ability>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cannot_follow: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cannot_precede: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub barriers: Vec<Point>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<PlatformPlacement>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collectibles: Vec<CollectibleArc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pits: Vec<Pit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ceilings: Vec<CeilingPlacement>,
+}
+
+impl SegmentDefinition {
+    fn can_follow(&self, previous: &SegmentDefinition) -> bool {
+        !self.cannot_follow.contains(&previous.name)
+            && !previous.cannot_precede.contains(&self.name)
+    }
+
+    fn available_with(&self, max_difficulty: u8, abilities: &Abilities) -> bool {
+        self.difficulty <= max_difficulty
+            && self.requires.map_or(true, |ability| abilities.has(ability))
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SegmentSet {
+    start: String,
+    platforms: HashMap<String, PlatformDefinition>,
+    segments: Vec<SegmentDefinition>,
+}
+
+impl TryFrom<wasm_bindgen::JsValue> for SegmentSet {
+    type Error = Error;
+
+    fn try_from(json: wasm_bindgen::JsValue) -> Result<Self> {
+        let segment_set: SegmentSet = serde_wasm_bindgen::from_value(json)
+            .map_err(|err| anyhow!("Could not deserialize json into a SegmentSet {:#?}", err))?;
+        segment_set.validate()
+    }
+}
+
+impl SegmentSet {
+    fn validate(self) -> Result<Self> {
+        if self.segment(&self.start).is_none() {
+            return Err(anyhow!("Starting segment {} not found", self.start));
+        }
+        self.segments
+            .iter()
+            .flat_map(|segment| {
+                segment
+                    .platforms
+                    .iter()
+                    .map(move |placement| (segment, placement))
+            })
+            .try_for_each(|(segment, placement)| {
+                if self.platforms.contains_key(&placement.platform) {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "Segment {} uses unknown platform {}",
+                        segment.name,
+                        placement.platform
+                    ))
+                }
+            })?;
+        self.segments
+            .iter()
+            .flat_map(|segment| {
+                segment
+                    .cannot_follow
+                    .iter()
+                    .chain(segment.cannot_precede.iter())
+                    .map(move |name| (segment, name))
+            })
+            .try_for_each(|(segment, name)| match self.segment(name) {
+                Some(_) => Ok(()),
+                None => Err(anyhow!(
+                    "Segment {} has a constraint on unknown segment {}",
+                    segment.name,
+                    name
+                )),
+            })?;
+
+        Ok(self)
+    }
+
+    pub fn segment(&self, name: &str) -> Option<&SegmentDefinition> {
+        self.segments.iter().find(|segment| segment.name == name)
+    }
+
+    pub fn starting_segment(&self) -> &SegmentDefinition {
+        self.segment(&self.start)
+            .expect("Starting segment is checked when loading")
+    }
+
+    pub fn platform(&self, name: &str) -> Option<&PlatformDefinition> {
+        self.platforms.get(name)
+    }
+
+    pub fn platform_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.platforms.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SegmentDefinition> {
+        self.segments.iter()
+    }
+
+    pub fn create(
+        &self,
+        segment: &SegmentDefinition,
+        stone: &HtmlImageElement,
+        sprite_sheet: &Rc<SpriteSheet>,
+        collectible_assets: &Rc<CollectibleAssets>,
+        ceiling_sheet: &Rc<SpriteSheet>,
+        offset_x: i16,
+    ) -> Vec<Box<dyn Obstacle>> {
+        let barriers = segment.barriers.iter().map(|barrier| -> Box<dyn Obstacle> {
+            Box::new(Barrier::new(Image::new(
+                stone.clone(),
+                Point {
+                    x: offset_x + barrier.x,
+                    y: barrier.y,
+                },
+            )))
+        });
+        let platforms = segment
+            .platforms
+            .iter()
+            .filter_map(|placement| {
+                self.platform(&placement.platform)
+                    .map(|definition| (placement, definition))
+            })
+            .map(|(placement, definition)| -> Box<dyn Obstacle> {
+                let platform = Platform::new(
+                    Rc::clone(sprite_sheet),
+                    Point {
+                        x: offset_x + placement.x,
+                        y: placement.y,
+                    },
+                    &definition.rows(placement.length),
+                );
+                match placement.path {
+                    Some(path) => Box::new(MovingPlatform::new(platform, path)),
+                    None => Box::new(platform),
+                }
+            });
+
+        let collectibles = segment
+            .collectibles
+            .iter()
+            .flat_map(|arc| arc.positions().map(move |position| (arc.kind, position)))
+            .map(|(kind, position)| -> Box<dyn Obstacle> {
+                Box::new(Collectible::new(
+                    Rc::clone(collectible_assets),
+                    kind,
+                    Point {
+                        x: offset_x + position.x,
+                        y: position.y,
+                    },
+                ))
+            });
+
+        let ceilings = segment.ceilings.iter().map(|ceiling| -> Box<dyn Obstacle> {
+            Box::new(Ceiling::new(
+                Rc::clone(ceiling_sheet),
+                ceiling.kind,
+                offset_x + ceiling.x,
+                ceiling.bottom,
+            ))
+        });
+
+        barriers
+            .chain(platforms)
+            .chain(collectibles)
+            .chain(ceilings)
+            .collect()
+    }
+}
+
+// Picks segments by weight, never placing a segment after one it can't follow
+// and never repeating the same segment more than MAX_REPEATS times in a row.
+#[derive(Default)]
+pub struct SegmentSelector {
+    previous: Option<String>,
+    repeats: u8,
+}
+
+impl SegmentSelector {
+    pub fn starting_with(segment: &SegmentDefinition) -> Self {
+        SegmentSelector {
+            previous: Some(segment.name.clone()),
+            repeats: 1,
+        }
+    }
+
+    pub fn next<'a>(
+        &mut self,
+        segment_set: &'a SegmentSet,
+        max_difficulty: u8,
+        abilities: &Abilities,
+        rng: &mut impl Rng,
+    ) -> Option<&'a SegmentDefinition> {
+        let previous = self
+            .previous
+            .as_ref()
+            .and_then(|name| segment_set.segment(name));
+        let candidates: Vec<&SegmentDefinition> = segment_set
+            .iter()
+            .filter(|segment| segment.available_with(max_difficulty, abilities))
+            .filter(|segment| previous.map_or(true, |previous| segment.can_follow(previous)))
+            .collect();
+        let not_repeated: Vec<&SegmentDefinition> = candidates
+            .iter()
+            .copied()
+            .filter(|segment| !self.repeated_too_often(segment))
+            .collect();
+        // Repeating is better than stopping, so only avoid repeats when
+        // there is something else to choose.
+        let candidates = if not_repeated.is_empty() {
+            candidates
+        } else {
+            not_repeated
+        };
+        let next = candidates
+            .choose_weighted(rng, |segment| segment.weight)
+            .ok()
+            .copied();
+        if let Some(segment) = next {
+            self.record(segment);
+        }
+        next
+    }
+
+    fn repeated_too_often(&self, segment: &SegmentDefinition) -> bool {
+        self.previous.as_ref() == Some(&segment.name) && self.repeats >= MAX_REPEATS
+    }
+
+    fn record(&mut self, segment: &SegmentDefinition) {
+        if self.previous.as_ref() == Some(&segment.name) {
+            self.repeats += 1;
+        } else {
+            self.previous = Some(segment.name.clone());
+            self.repeats = 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn segment(name: &str, difficulty: u8, cannot_follow: &[&str]) -> SegmentDefinition {
+        SegmentDefinition {
+            name: name.into(),
+            difficulty,
+            weight: 1,
+            requires: None,
+            cannot_follow: cannot_follow.iter().map(|name| name.to_string()).collect(),
+            cannot_precede: vec![],
+            barriers: vec![],
+            platforms: vec![],
+            collectibles: vec![],
+            pits: vec![],
+            ceilings: vec![],
+        }
+    }
+
+    fn segment_set(segments: Vec<SegmentDefinition>) -> SegmentSet {
+        SegmentSet {
+            start: segments[0].name.clone(),
+            platforms: HashMap::new(),
+            segments,
+        }
+        .validate()
+        .unwrap()
+    }
+
+    #[test]
+    fn selector_respects_constraints_difficulty_and_repeats() {
+        let segment_set = segment_set(vec![
+            segment("high_platform", 1, &[]),
+            segment("ground_stone", 1, &["high_platform"]),
+            segment("stones", 1, &[]),
+            segment("hard", 2, &[]),
+        ]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut selector = SegmentSelector::starting_with(segment_set.starting_segment());
+        let mut previous = segment_set.starting_segment().name.clone();
+        let mut repeats = 1;
+
+        for _ in 0..200 {
+            let next = selector
+                .next(&segment_set, 1, &Abilities::default(), &mut rng)
+                .unwrap();
+            assert_ne!(next.name, "hard");
+            assert!(!(previous == "high_platform" && next.name == "ground_stone"));
+            repeats = if next.name == previous {
+                repeats + 1
+            } else {
+                1
+            };
+            assert!(repeats <= MAX_REPEATS);
+            previous = next.name.clone();
+        }
+    }
+
+    #[test]
+    fn shipped_segments_never_run_a_high_platform_into_a_stone_wall() {
+        let segment_set: SegmentSet =
+            serde_json::from_str(include_str!("../static/segments.json")).unwrap();
+        let segment_set = segment_set.validate().unwrap();
+        let mut abilities = Abilities::default();
+        abilities.unlock(Ability::DoubleJump);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut selector = SegmentSelector::starting_with(segment_set.starting_segment());
+        let mut previous = segment_set.starting_segment().name.clone();
+
+        for _ in 0..500 {
+            let next = selector
+                .next(&segment_set, u8::MAX, &abilities, &mut rng)
+                .unwrap();
+            assert!(
+                !(previous == "stone_and_platform" && next.name == "stone_wall"),
+                "stone_wall followed stone_and_platform"
+            );
+            previous = next.name.clone();
+        }
+    }
+
+    #[test]
+    fn unknown_constraints_are_rejected() {
+        let segment_set = SegmentSet {
+            start: "ground_stone".into(),
+            platforms: HashMap::new(),
+            segments: vec![segment("ground_stone", 1, &["missing"])],
+        };
+        assert!(segment_set.validate().is_err());
+    }
+
+    #[test]
+    fn platform_rows_repeat_the_middle_and_fill_pieces() {
+        let row = |left: &str, middle: &str, right: &str| PlatformRow {
+            left: left.into(),
+            middle: middle.into(),
+            right: right.into(),
+        };
+        let definition = PlatformDefinition {
+            top: row("1.png", "2.png", "7.png"),
+            fill: Some(row("4.png", "5.png", "6.png")),
+            length: 1,
+            depth: 3,
+        };
+
+        assert_eq!(
+            definition.rows(Some(2)),
+            vec![
+                vec!["1.png", "2.png", "2.png", "7.png"],
+                vec!["4.png", "5.png", "5.png", "6.png"],
+                vec!["4.png", "5.png", "5.png", "6.png"],
+            ]
+        );
+        assert_eq!(definition.rows(None)[0], vec!["1.png", "2.png", "7.png"]);
+    }
+
+    #[test]
+    fn platform_paths_ease_out_and_back_each_period() {
+        let path = PlatformPath {
+            axis: Axis::Vertical,
+            distance: -100,
+            period: 40,
+        };
+
+        assert_eq!(path.offset(0).y, 0);
+        assert_eq!(path.offset(10).y, -50);
+        assert_eq!(path.offset(20).y, -100);
+        assert_eq!(path.offset(40).y, 0);
+        assert_eq!(path.offset(20).x, 0);
+        // A quarter of the way out a linear path would be at -25, but the
+        // eased one is still near where it started, on the way out and back.
+        assert_eq!(path.offset(5).y, -15);
+        assert_eq!(path.offset(35).y, -15);
+    }
+
+    #[test]
+    fn collectible_arcs_follow_the_jump_curve() {
+        let arc = CollectibleArc {
+            kind: CollectibleKind::Coin,
+            x: 100,
+            y: 500,
+            count: 7,
+            spacing: 8,
+        };
+        let positions: Vec<_> = arc.positions().collect();
+
+        assert_eq!(positions.len(), 7);
+        assert_eq!((positions[0].x, positions[0].y), (100, 500));
+        assert!(positions.windows(2).all(|pair| pair[1].x > pair[0].x));
+        let highest = positions.iter().map(|position| position.y).min().unwrap();
+        assert!(highest < positions[0].y && highest < positions[6].y);
+    }
 }
@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+
+use crate::browser;
+
+const STORAGE_KEY: &str = "walk_the_dog.settings";
+const MAX_VOLUME: u8 = 10;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn lives(&self) -> u8 {
+        match self {
+            Difficulty::Easy => 5,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 1,
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    fn step(&self, step: i8) -> Self {
+        const ORDER: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+        let index = ORDER
+            .iter()
+            .position(|difficulty| difficulty == self)
+            .unwrap_or(1);
+        ORDER[(index as i8 + step).rem_euclid(ORDER.len() as i8) as usize]
+    }
+}
+
+// Key codes, as in `KeyboardEvent.code`, for each of the boy's moves. The
+// slide key also triggers his air move.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct KeyBindings {
+    pub run: String,
+    pub jump: String,
+    pub slide: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            run: "ArrowRight".into(),
+            jump: "Space".into(),
+            slide: "ArrowDown".into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Setting {
+    MusicVolume,
+    EffectsVolume,
+    RunKey,
+    JumpKey,
+    SlideKey,
+    ShowFps,
+    ReducedMotion,
+    Difficulty,
+}
+
+pub const SETTINGS: [Setting; 8] = [
+    Setting::MusicVolume,
+    Setting::EffectsVolume,
+    Setting::RunKey,
+    Setting::JumpKey,
+    Setting::SlideKey,
+    Setting::ShowFps,
+    Setting::ReducedMotion,
+    Setting::Difficulty,
+];
+
+impl Setting {
+    pub fn is_key(&self) -> bool {
+        matches!(self, Setting::RunKey | Setting::JumpKey | Setting::SlideKey)
+    }
+}
+
+// The player's preferences, kept in the browser's local storage between
+// visits. Anything missing from what was stored, such as a setting added
+// since, takes its default.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub music_volume: u8,
+    pub effects_volume: u8,
+    pub keys: KeyBindings,
+    pub show_fps: bool,
+    pub reduced_motion: bool,
+    pub difficulty: Difficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            music_volume: 6,
+            effects_volume: 8,
+            keys: KeyBindings::default(),
+            show_fps: false,
+            reduced_motion: false,
+            difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        browser::load(STORAGE_KEY)
+            .unwrap_or_else(|err| {
+                error!("Could not load settings {:#?}", err);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = browser::store(STORAGE_KEY, self) {
+            error!("Could not save settings {:#?}", err);
+        }
+    }
+
+    pub fn music_gain(&self) -> f32 {
+        f32::from(self.music_volume) / f32::from(MAX_VOLUME)
+    }
+
+    pub fn effects_gain(&self) -> f32 {
+        f32::from(self.effects_volume) / f32::from(MAX_VOLUME)
+    }
+
+    pub fn describe(&self, setting: Setting) -> String {
+        let on_off = |value: bool| if value { "On" } else { "Off" };
+        match setting {
+            Setting::MusicVolume => format!("Music volume: {}", self.music_volume),
+            Setting::EffectsVolume => format!("Effects volume: {}", self.effects_volume),
+            Setting::RunKey => format!("Run key: {}", key_name(&self.keys.run)),
+            Setting::JumpKey => format!("Jump key: {}", key_name(&self.keys.jump)),
+            Setting::SlideKey => format!("Slide key: {}", key_name(&self.keys.slide)),
+            Setting::ShowFps => format!("Show FPS: {}", on_off(self.show_fps)),
+            Setting::ReducedMotion => format!("Reduced motion: {}", on_off(self.reduced_motion)),
+            Setting::Difficulty => format!("Difficulty: {}", self.difficulty.label()),
+        }
+    }
+
+    // Moves a setting up or down a step. Volumes stop at either end while
+    // everything else wraps around.
+    pub fn adjust(&mut self, setting: Setting, step: i8) {
+        let volume = |volume: u8| volume.saturating_add_signed(step).min(MAX_VOLUME);
+        match setting {
+            Setting::MusicVolume => self.music_volume = volume(self.music_volume),
+            Setting::EffectsVolume => self.effects_volume = volume(self.effects_volume),
+            Setting::ShowFps => self.show_fps = !self.show_fps,
+            Setting::ReducedMotion => self.reduced_motion = !self.reduced_motion,
+            Setting::Difficulty => self.difficulty = self.difficulty.step(step),
+            Setting::RunKey | Setting::JumpKey | Setting::SlideKey => {}
+        }
+    }
+
+    // Binds a move to a key. A key can only do one thing, so whichever move
+    // had it before takes over the old key of the one being rebound.
+    pub fn bind(&mut self, setting: Setting, code: &str) {
+        let keys = &mut self.keys;
+        let previous = match setting {
+            Setting::RunKey => keys.run.clone(),
+            Setting::JumpKey => keys.jump.clone(),
+            Setting::SlideKey => keys.slide.clone(),
+            _ => return,
+        };
+        [&mut keys.run, &mut keys.jump, &mut keys.slide]
+            .into_iter()
+            .filter(|key| key.as_str() == code)
+            .for_each(|key| *key = previous.clone());
+        match setting {
+            Setting::RunKey => keys.run = code.into(),
+            Setting::JumpKey => keys.jump = code.into(),
+            _ => keys.slide = code.into(),
+        }
+    }
+}
+
+pub fn key_name(code: &str) -> &str {
+    code.strip_prefix("Key")
+        .or_else(|| code.strip_prefix("Digit"))
+        .unwrap_or(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volumes_stop_at_the_ends_and_choices_wrap() {
+        let mut settings = Settings::default();
+        (0..20).for_each(|_| settings.adjust(Setting::MusicVolume, 1));
+        assert_eq!(settings.music_volume, MAX_VOLUME);
+        (0..20).for_each(|_| settings.adjust(Setting::EffectsVolume, -1));
+        assert_eq!(settings.effects_volume, 0);
+
+        settings.adjust(Setting::Difficulty, 1);
+        assert_eq!(settings.difficulty, Difficulty::Hard);
+        settings.adjust(Setting::Difficulty, 1);
+        assert_eq!(settings.difficulty, Difficulty::Easy);
+        settings.adjust(Setting::Difficulty, -1);
+        assert_eq!(settings.difficulty, Difficulty::Hard);
+    }
+
+    #[test]
+    fn binding_a_key_in_use_swaps_the_two_moves() {
+        let mut settings = Settings::default();
+        settings.bind(Setting::JumpKey, "ArrowUp");
+        assert_eq!(settings.keys.jump, "ArrowUp");
+
+        settings.bind(Setting::SlideKey, "ArrowUp");
+        assert_eq!(settings.keys.slide, "ArrowUp");
+        assert_eq!(settings.keys.jump, "ArrowDown");
+        assert_eq!(settings.describe(Setting::RunKey), "Run key: ArrowRight");
+    }
+
+    #[test]
+    fn missing_settings_take_their_defaults() {
+        let settings: Settings = serde_json::from_str(
+            r#"{ "musicVolume": 2, "keys": { "run": "KeyD", "jump": "KeyW", "slide": "KeyS" } }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.music_volume, 2);
+        assert_eq!(settings.describe(Setting::JumpKey), "Jump key: W");
+        assert_eq!(settings.effects_volume, Settings::default().effects_volume);
+        assert_eq!(settings.difficulty, Difficulty::Normal);
+    }
+}
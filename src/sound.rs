@@ -2,7 +2,7 @@ use anyhow::{anyhow, Result};
 use js_sys::ArrayBuffer;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{AudioBuffer, AudioBufferSourceNode, AudioContext, AudioDestinationNode, AudioNode};
+use web_sys::{AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, GainNode};
 
 pub fn create_audio_context() -> Result<AudioContext> {
     AudioContext::new().map_err(|err| anyhow!("Could not create audio context: {:#?}", err))
@@ -13,29 +13,45 @@ fn create_buffer_source(ctx: &AudioContext) -> Result<AudioBufferSourceNode> {
         .map_err(|err| anyhow!("Error creating buffer source {:#?}", err))
 }
 
-fn connect_with_audio_node(
-    buffer_source: &AudioBufferSourceNode,
-    destination: &AudioDestinationNode,
-) -> Result<AudioNode> {
-    buffer_source
+fn connect_with_audio_node(source: &AudioNode, destination: &AudioNode) -> Result<AudioNode> {
+    source
         .connect_with_audio_node(destination)
         .map_err(|err| anyhow!("Error connecting audio source to destination {:#?}", err))
 }
 
-fn create_track_souce(ctx: &AudioContext, buffer: &AudioBuffer) -> Result<AudioBufferSourceNode> {
+fn create_track_souce(
+    ctx: &AudioContext,
+    buffer: &AudioBuffer,
+    destination: &AudioNode,
+) -> Result<AudioBufferSourceNode> {
     let track_source = create_buffer_source(ctx)?;
     track_source.set_buffer(Some(buffer));
-    connect_with_audio_node(&track_source, &ctx.destination())?;
+    connect_with_audio_node(&track_source, destination)?;
     Ok(track_source)
 }
 
+// A volume control that everything played through it passes through on the
+// way to the speakers.
+pub fn create_gain(ctx: &AudioContext) -> Result<GainNode> {
+    let gain = ctx
+        .create_gain()
+        .map_err(|err| anyhow!("Error creating gain node {:#?}", err))?;
+    connect_with_audio_node(&gain, &ctx.destination())?;
+    Ok(gain)
+}
+
 pub enum Looping {
     No,
     Yes,
 }
 
-pub fn play_sound(ctx: &AudioContext, buffer: &AudioBuffer, looping: Looping) -> Result<()> {
-    let track_source = create_track_souce(ctx, buffer)?;
+pub fn play_sound(
+    ctx: &AudioContext,
+    buffer: &AudioBuffer,
+    destination: &AudioNode,
+    looping: Looping,
+) -> Result<()> {
+    let track_source = create_track_souce(ctx, buffer, destination)?;
     if matches!(looping, Looping::Yes) {
         track_source.set_loop(true);
     }
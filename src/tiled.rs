@@ -0,0 +1,403 @@
+use std::rc::Rc;
+
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+use web_sys::HtmlImageElement;
+
+use crate::engine::{Image, Point, SheetRect, SpriteSheet};
+use crate::game::{Barrier, Obstacle, Platform, GROUND};
+use crate::segments::Pit;
+
+// Tiled keeps the flip and rotation flags of a tile in the top bits of its gid.
+const TILE_FLAGS: u32 = 0xF000_0000;
+const EMPTY_TILE: u32 = 0;
+
+#[derive(Deserialize)]
+struct TiledMap {
+    height: usize,
+    tilewidth: i16,
+    tileheight: i16,
+    layers: Vec<Layer>,
+    tilesets: Vec<Tileset>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Layer {
+    #[serde(rename = "tilelayer")]
+    Tiles(TileLayer),
+    #[serde(rename = "objectgroup")]
+    Objects(ObjectGroup),
+    #[serde(other)]
+    Unsupported,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct TileLayer {
+    name: String,
+    width: usize,
+    data: Vec<u32>,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    #[serde(default)]
+    offsetx: f64,
+    #[serde(default)]
+    offsety: f64,
+}
+
+#[derive(Deserialize)]
+struct ObjectGroup {
+    objects: Vec<MapObject>,
+    #[serde(default = "default_visible")]
+    visible: bool,
+}
+
+#[derive(Deserialize)]
+struct MapObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    gid: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Tileset {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TilesetTile>,
+}
+
+#[derive(Deserialize)]
+struct TilesetTile {
+    id: u32,
+    image: String,
+}
+
+#[derive(Clone)]
+pub struct LevelPlatform {
+    pub sprites: Vec<String>,
+    pub position: Point,
+}
+
+#[derive(Clone)]
+pub struct GoalFlag {
+    pub bounds: SheetRect,
+    pub sprite: Option<String>,
+}
+
+// A finite course built in the Tiled map editor. Tile layers are made from
+// image collection tilesets whose file names match the cells in tiles.json,
+// and object layers hold barriers, pits, the spawn point and the goal flag,
+// told apart by their type (or name when the type is empty).
+//
+// Positions are moved so the spawn point lines up with the start of the
+// screen and the bottom of the map lines up with the top of the ground.
+#[derive(Clone)]
+pub struct Level {
+    pub platforms: Vec<LevelPlatform>,
+    pub barriers: Vec<Point>,
+    pub pits: Vec<Pit>,
+    pub goal: GoalFlag,
+}
+
+impl TryFrom<wasm_bindgen::JsValue> for Level {
+    type Error = Error;
+
+    fn try_from(json: wasm_bindgen::JsValue) -> Result<Self> {
+        let map: TiledMap = serde_wasm_bindgen::from_value(json)
+            .map_err(|err| anyhow!("Could not deserialize json into a Tiled map {:#?}", err))?;
+        Level::from_map(map)
+    }
+}
+
+impl Level {
+    fn from_map(map: TiledMap) -> Result<Self> {
+        let tile_names = TileNames::new(&map.tilesets)?;
+        let offset_y = GROUND - map.height as i16 * map.tileheight;
+
+        let objects: Vec<&MapObject> = map
+            .layers
+            .iter()
+            .filter_map(|layer| match layer {
+                Layer::Objects(group) if group.visible => Some(group.objects.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let spawn_x = objects
+            .iter()
+            .find(|object| object.kind() == "spawn")
+            .map_or(0, |object| object.x as i16);
+        let offset = Point {
+            x: -spawn_x,
+            y: offset_y,
+        };
+
+        let platforms = map
+            .layers
+            .iter()
+            .filter_map(|layer| match layer {
+                Layer::Tiles(tiles) if tiles.visible => Some(tiles),
+                _ => None,
+            })
+            .map(|layer| layer.platforms(&map, &tile_names, offset))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        let barriers = objects
+            .iter()
+            .filter(|object| object.kind() == "barrier")
+            .map(|object| object.top_left(offset))
+            .collect();
+        let pits = objects
+            .iter()
+            .filter(|object| object.kind() == "pit")
+            .map(|object| Pit {
+                x: object.top_left(offset).x,
+                width: object.width as i16,
+            })
+            .collect();
+        let goal = objects
+            .iter()
+            .find(|object| object.kind() == "goal")
+            .map(|object| {
+                let top_left = object.top_left(offset);
+                GoalFlag {
+                    bounds: SheetRect {
+                        x: top_left.x,
+                        y: top_left.y,
+                        w: object.width as i16,
+                        h: object.height as i16,
+                    },
+                    sprite: object
+                        .gid
+                        .and_then(|gid| tile_names.name(gid))
+                        .map(String::from),
+                }
+            })
+            .ok_or_else(|| anyhow!("The level has no goal object"))?;
+
+        Ok(Level {
+            platforms,
+            barriers,
+            pits,
+            goal,
+        })
+    }
+
+    pub fn create(
+        &self,
+        stone: &HtmlImageElement,
+        sprite_sheet: &Rc<SpriteSheet>,
+    ) -> Vec<Box<dyn Obstacle>> {
+        let barriers = self.barriers.iter().map(|barrier| -> Box<dyn Obstacle> {
+            Box::new(Barrier::new(Image::new(stone.clone(), *barrier)))
+        });
+        let platforms = self.platforms.iter().map(|platform| -> Box<dyn Obstacle> {
+            let sprite_names: Vec<&str> = platform.sprites.iter().map(String::as_str).collect();
+            Box::new(Platform::new(
+                Rc::clone(sprite_sheet),
+                platform.position,
+                &[sprite_names],
+            ))
+        });
+
+        barriers.chain(platforms).collect()
+    }
+}
+
+impl TileLayer {
+    // Each unbroken row of tiles becomes a single platform, so the boy can
+    // run along it without catching on the edges between tiles.
+    fn platforms(
+        &self,
+        map: &TiledMap,
+        tile_names: &TileNames,
+        offset: Point,
+    ) -> Result<Vec<LevelPlatform>> {
+        let mut platforms = vec![];
+        for (row, tiles) in self.data.chunks(self.width.max(1)).enumerate() {
+            let mut current: Option<LevelPlatform> = None;
+            for (column, gid) in tiles.iter().enumerate() {
+                let sprite =
+                    match gid & !TILE_FLAGS {
+                        EMPTY_TILE => None,
+                        gid => Some(tile_names.name(gid).ok_or_else(|| {
+                            anyhow!("Layer {} uses unknown tile {}", self.name, gid)
+                        })?),
+                    };
+                match (sprite, current.as_mut()) {
+                    (Some(sprite), Some(platform)) => platform.sprites.push(sprite.into()),
+                    (Some(sprite), None) => {
+                        current = Some(LevelPlatform {
+                            sprites: vec![sprite.into()],
+                            position: Point {
+                                x: offset.x + self.offsetx as i16 + column as i16 * map.tilewidth,
+                                y: offset.y + self.offsety as i16 + row as i16 * map.tileheight,
+                            },
+                        })
+                    }
+                    (None, _) => platforms.extend(current.take()),
+                }
+            }
+            platforms.extend(current);
+        }
+        Ok(platforms)
+    }
+}
+
+impl MapObject {
+    fn kind(&self) -> &str {
+        if self.kind.is_empty() {
+            &self.name
+        } else {
+            &self.kind
+        }
+    }
+
+    // Tile objects are anchored at their bottom left, everything else at
+    // the top left.
+    fn top_left(&self, offset: Point) -> Point {
+        let y = match self.gid {
+            Some(_) => self.y - self.height,
+            None => self.y,
+        };
+        Point {
+            x: offset.x + self.x as i16,
+            y: offset.y + y as i16,
+        }
+    }
+}
+
+struct TileNames<'a> {
+    tilesets: &'a [Tileset],
+}
+
+impl<'a> TileNames<'a> {
+    fn new(tilesets: &'a [Tileset]) -> Result<Self> {
+        match tilesets.iter().find_map(|tileset| tileset.source.as_ref()) {
+            Some(source) => Err(anyhow!(
+                "External tileset {} is not supported, embed it in the map",
+                source
+            )),
+            None => Ok(TileNames { tilesets }),
+        }
+    }
+
+    // The tiles.json cell for a gid, which is the file name of the tile's
+    // image in its tileset.
+    fn name(&self, gid: u32) -> Option<&'a str> {
+        let gid = gid & !TILE_FLAGS;
+        let tileset = self
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.firstgid <= gid)
+            .max_by_key(|tileset| tileset.firstgid)?;
+        tileset
+            .tiles
+            .iter()
+            .find(|tile| tile.id == gid - tileset.firstgid)
+            .map(|tile| tile.image.rsplit('/').next().unwrap_or(&tile.image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(data: &str, objects: &str) -> TiledMap {
+        serde_json::from_str(&format!(
+            r#"{{
+                "width": 4, "height": 3, "tilewidth": 128, "tileheight": 128,
+                "layers": [
+                    {{ "type": "tilelayer", "name": "ground", "width": 4, "height": 3,
+                       "data": {} }},
+                    {{ "type": "objectgroup", "name": "objects", "objects": {} }},
+                    {{ "type": "imagelayer", "name": "sky" }}
+                ],
+                "tilesets": [{{
+                    "firstgid": 1,
+                    "tiles": [
+                        {{ "id": 0, "image": "../tiles/13.png" }},
+                        {{ "id": 1, "image": "../tiles/14.png" }},
+                        {{ "id": 2, "image": "../tiles/15.png" }}
+                    ]
+                }}]
+            }}"#,
+            data, objects
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn tile_rows_and_objects_become_level_geometry() {
+        let level = Level::from_map(map(
+            "[0, 0, 0, 0,  1, 2, 0, 3,  0, 0, 0, 0]",
+            r#"[
+                { "type": "spawn", "x": 128, "y": 300 },
+                { "class": "barrier", "x": 300, "y": 330, "width": 90, "height": 54 },
+                { "type": "pit", "x": 640, "y": 384, "width": 150, "height": 0 },
+                { "name": "goal", "x": 500, "y": 384, "width": 64, "height": 64, "gid": 2147483650 }
+            ]"#,
+        ))
+        .unwrap();
+
+        let platforms: Vec<_> = level
+            .platforms
+            .iter()
+            .map(|platform| {
+                (
+                    platform.sprites.join(","),
+                    platform.position.x,
+                    platform.position.y,
+                )
+            })
+            .collect();
+        assert_eq!(
+            platforms,
+            vec![
+                ("13.png,14.png".to_string(), -128, 294),
+                ("15.png".to_string(), 256, 294),
+            ]
+        );
+        assert_eq!(level.barriers[0].x, 172);
+        assert_eq!(level.barriers[0].y, 496);
+        assert_eq!(level.pits[0].x, 512);
+        assert_eq!(level.pits[0].width, 150);
+        assert_eq!(level.goal.bounds.x, 372);
+        assert_eq!(level.goal.bounds.y, 486);
+        assert_eq!(level.goal.sprite.as_deref(), Some("14.png"));
+    }
+
+    #[test]
+    fn shipped_level_loads() {
+        let map: TiledMap =
+            serde_json::from_str(include_str!("../static/levels/level1.json")).unwrap();
+        let level = Level::from_map(map).unwrap();
+        assert_eq!(level.platforms.len(), 2);
+        assert_eq!(level.barriers.len(), 3);
+    }
+
+    #[test]
+    fn levels_need_a_goal_and_known_tiles() {
+        assert!(Level::from_map(map("[0,0,0,0,0,0,0,0,0,0,0,0]", "[]")).is_err());
+        assert!(Level::from_map(map(
+            "[9,0,0,0,0,0,0,0,0,0,0,0]",
+            r#"[{ "type": "goal", "x": 500, "y": 300 }]"#
+        ))
+        .is_err());
+    }
+}